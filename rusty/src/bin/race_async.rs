@@ -14,7 +14,7 @@ async fn print_after(i: usize, after: Duration, sender: Sender<usize>) {
     }
 }
 
-fn shuffle(v: &mut Vec<i32>) {
+fn shuffle(v: &mut [i32]) {
     for i in 0..v.len() {
         let j = i + random::<usize>() % (v.len() - i);
         if i != j {
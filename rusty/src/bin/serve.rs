@@ -3,6 +3,7 @@ extern crate rusty;
 use serde::Serialize;
 use warp::Filter;
 
+#[allow(dead_code)]
 #[derive(Serialize)]
 struct S {
     i: i32,
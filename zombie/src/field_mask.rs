@@ -0,0 +1,160 @@
+use crate::{wkt::FieldMask, MergeField};
+
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidFieldPath(pub String);
+
+impl fmt::Display for InvalidFieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid field path: `{}`", self.0)
+    }
+}
+
+impl Error for InvalidFieldPath {}
+
+/// Field mask operations for derived messages.
+pub trait Masked: MergeField + Sized {
+    /// Checks every path in `mask` against this message's field names.
+    fn validate_mask(mask: &FieldMask) -> Result<(), InvalidFieldPath> {
+        match mask.paths.iter().find(|path| !Self::is_valid_subpath(path)) {
+            Some(path) => Err(InvalidFieldPath(path.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Clears every field that isn't named by `mask`.
+    fn apply_mask(&mut self, mask: &FieldMask) {
+        let paths = mask.paths.iter().map(String::as_str).collect::<Vec<&str>>();
+        self.apply_subpaths(&paths);
+    }
+
+    /// Copies the fields named by `mask` from `src`, replacing what was there.
+    fn merge_masked(&mut self, src: &Self, mask: &FieldMask) {
+        let paths = mask.paths.iter().map(String::as_str).collect::<Vec<&str>>();
+        self.merge_subpaths(src, &paths);
+    }
+}
+
+impl<T: MergeField> Masked for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Deserialize, DeserializeField, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Inner {
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        string: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Outer {
+        #[id(1)]
+        name: String,
+
+        #[id(2)]
+        repeated: Vec<i32>,
+
+        #[id(3)]
+        submessage: Option<Inner>,
+
+        #[id(4)]
+        inner: Inner,
+    }
+
+    fn example() -> Outer {
+        Outer {
+            name: "outer".to_owned(),
+            repeated: vec![1, 2],
+            submessage: Some(Inner {
+                int32: 150,
+                string: "sub".to_owned(),
+            }),
+            inner: Inner {
+                int32: 151,
+                string: "inner".to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    fn validate_mask() {
+        assert_eq!(
+            Ok(()),
+            Outer::validate_mask(&FieldMask::new(["name", "submessage.int32", "inner"]))
+        );
+        assert_eq!(
+            Err(InvalidFieldPath("submessage.missing".to_owned())),
+            Outer::validate_mask(&FieldMask::new(["name", "submessage.missing"]))
+        );
+        assert!(Outer::validate_mask(&FieldMask::new(["name.length"])).is_err());
+        assert!(Outer::validate_mask(&FieldMask::new(["repeated.int32"])).is_err());
+        assert!(Outer::validate_mask(&FieldMask::new([""])).is_err());
+    }
+
+    #[test]
+    fn apply_mask() {
+        let mut message = example();
+        message.apply_mask(&FieldMask::new(["repeated", "submessage.int32"]));
+        assert_eq!(
+            Outer {
+                name: "".to_owned(),
+                repeated: vec![1, 2],
+                submessage: Some(Inner {
+                    int32: 150,
+                    string: "".to_owned(),
+                }),
+                inner: Inner {
+                    int32: 0,
+                    string: "".to_owned(),
+                },
+            },
+            message
+        );
+    }
+
+    #[test]
+    fn merge_masked() {
+        let mut message = example();
+        let src = Outer {
+            name: "patched".to_owned(),
+            repeated: vec![3],
+            submessage: None,
+            inner: Inner {
+                int32: 0,
+                string: "patched".to_owned(),
+            },
+        };
+        message.merge_masked(&src, &FieldMask::new(["name", "repeated", "inner.int32"]));
+        assert_eq!("patched", message.name);
+        assert_eq!(vec![3], message.repeated);
+        assert_eq!(0, message.inner.int32);
+        assert_eq!("inner", message.inner.string);
+        assert_eq!(example().submessage, message.submessage);
+    }
+
+    #[test]
+    fn merge_masked_submessage() {
+        let mut message = example();
+        message.submessage = None;
+        message.merge_masked(&example(), &FieldMask::new(["submessage.string"]));
+        assert_eq!(
+            Some(Inner {
+                int32: 0,
+                string: "sub".to_owned(),
+            }),
+            message.submessage
+        );
+
+        message.merge_masked(
+            &Outer::decode_from_slice(&[]).unwrap(),
+            &FieldMask::new(["submessage"]),
+        );
+        assert_eq!(None, message.submessage);
+    }
+}
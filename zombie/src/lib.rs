@@ -1,26 +1,91 @@
+extern crate self as zombie;
+
+pub mod field_mask;
+pub mod registry;
+pub mod wkt;
+
+pub use field_mask::InvalidFieldPath;
+pub use field_mask::Masked;
+pub use registry::AnyMessage;
+pub use registry::TypeRegistry;
+pub use zombie_core::builder::Buildable;
+pub use zombie_core::builder::Builder;
+pub use zombie_core::builder::BuiltWith;
+pub use zombie_core::deserialize::read_field_value;
+pub use zombie_core::deserialize::read_len;
 pub use zombie_core::deserialize::read_tag;
 pub use zombie_core::deserialize::read_uvarint;
+pub use zombie_core::deserialize::skip_field;
+pub use zombie_core::deserialize::uvarint_len;
 pub use zombie_core::deserialize::Deserialize;
 pub use zombie_core::deserialize::DeserializeError;
 pub use zombie_core::deserialize::DeserializeField;
+pub use zombie_core::deserialize::OffsetReader;
+pub use zombie_core::deserialize::ProtoDefault;
+pub use zombie_core::deserialize::RecursionGuard;
+pub use zombie_core::deserialize::RECURSION_LIMIT;
+pub use zombie_core::diff::field_path;
+pub use zombie_core::diff::Diff;
+pub use zombie_core::diff::FieldChange;
+pub use zombie_core::diff::MessageDiff;
+pub use zombie_core::merge::field_subpaths;
+pub use zombie_core::merge::MergeField;
+pub use zombie_core::message::Message;
+pub use zombie_core::name::Name;
 pub use zombie_core::proto_type::ProtoType;
 pub use zombie_core::proto_type::WireType;
+pub use zombie_core::redact::redacting;
+pub use zombie_core::redact::unredacted;
+pub use zombie_core::redact::Redacted;
+pub use zombie_core::redact::REDACTED;
+pub use zombie_core::serialize::is_default_encoding;
+pub use zombie_core::serialize::write_field_header;
 pub use zombie_core::serialize::write_tag;
 pub use zombie_core::serialize::write_uvarint;
 pub use zombie_core::serialize::Serialize;
+pub use zombie_core::validate::matches_regex;
+pub use zombie_core::validate::Length;
+pub use zombie_core::validate::Validate;
+pub use zombie_core::validate::ValidationErrors;
+pub use zombie_core::validate::Violation;
+pub use zombie_core::wire;
 pub use zombie_macro::Deserialize;
+pub use zombie_macro::Diff;
+pub use zombie_macro::Name;
 pub use zombie_macro::Serialize;
 
+/// Asserts that two messages are equal, printing their field-by-field diff if not.
+#[macro_export]
+macro_rules! assert_message_eq {
+    ($left:expr, $right:expr $(,)?) => {{
+        let diff = $crate::Diff::diff(&$left, &$right);
+        if !diff.is_empty() {
+            panic!("assertion failed: messages differ\n{}", diff);
+        }
+    }};
+    ($left:expr, $right:expr, $($arg:tt)+) => {{
+        let diff = $crate::Diff::diff(&$left, &$right);
+        if !diff.is_empty() {
+            panic!("assertion failed: {}\n{}", format_args!($($arg)+), diff);
+        }
+    }};
+}
+
 #[cfg(test)]
+#[allow(clippy::approx_constant)]
 mod tests {
     mod zombie {
         pub use super::super::*;
     }
     use zombie::Deserialize;
     use zombie::DeserializeField;
+    use zombie::Diff;
+    use zombie::Message;
+    use zombie::ProtoDefault;
     use zombie::Serialize;
 
-    #[derive(Copy, Clone, Serialize, Deserialize)]
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Copy, Clone, Serialize, Diff, Deserialize)]
     enum TestEnum {
         VariantZero = 0,
         VariantOne = 1,
@@ -43,13 +108,15 @@ mod tests {
         }
     }
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Clone, Serialize, Diff, Deserialize)]
+    #[zombie(builder, debug)]
     struct SubMessage {
         #[id(1)]
         int32: i32,
     }
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Serialize, Diff, Deserialize)]
+    #[zombie(builder)]
     struct TestMessage {
         #[id(1)]
         int32: i32,
@@ -112,7 +179,7 @@ mod tests {
         repeated: Vec<u32>,
     }
 
-    #[derive(Serialize)]
+    #[derive(Serialize, Diff, Deserialize)]
     struct TestOptionalMessage {
         #[id(1)]
         int32: Option<i32>,
@@ -172,6 +239,419 @@ mod tests {
         submessage: Option<SubMessage>,
     }
 
+    #[derive(Serialize, Deserialize)]
+    struct TestProto2Message {
+        #[id(1)]
+        #[required]
+        int32: Option<i32>,
+
+        #[id(2)]
+        #[required]
+        string: String,
+
+        #[id(3)]
+        #[default(42)]
+        answer: i32,
+
+        #[id(4)]
+        #[default("x")]
+        label: String,
+
+        #[id(5)]
+        #[default(-7)]
+        negative: Option<i64>,
+    }
+
+    #[derive(Serialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestProto3Message {
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        #[pbtype(sint64)]
+        sint64: i64,
+
+        #[id(3)]
+        boolean: bool,
+
+        #[id(4)]
+        double: f64,
+
+        #[id(5)]
+        string: String,
+
+        #[id(6)]
+        bytes: Vec<u8>,
+
+        #[id(7)]
+        enumeration: TestEnum,
+
+        #[id(8)]
+        repeated: Vec<u32>,
+
+        #[id(9)]
+        optional: Option<i32>,
+
+        #[id(10)]
+        submessage: SubMessage,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[reserved(2, 15, 9 to 11, 20 to max, "old_name")]
+    struct TestReservedMessage {
+        #[id(1)]
+        a: i32,
+
+        #[id(12)]
+        b: i32,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    #[zombie(builder)]
+    struct TestEnvelope<T> {
+        #[id(1)]
+        kind: String,
+
+        #[id(2)]
+        payload: Option<T>,
+    }
+
+    #[derive(Serialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestView<'a> {
+        #[id(1)]
+        name: &'a str,
+
+        #[id(2)]
+        data: &'a [u8],
+
+        #[id(3)]
+        tags: Vec<&'a str>,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    struct TestBatch<T>
+    where
+        T: Clone,
+    {
+        #[id(1)]
+        items: Vec<T>,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    struct TestTreeNode {
+        #[id(1)]
+        value: i32,
+
+        #[id(2)]
+        left: Option<Box<TestTreeNode>>,
+
+        #[id(3)]
+        children: Vec<TestTreeNode>,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestPointerMessage<'a> {
+        #[id(1)]
+        shared: std::sync::Arc<SubMessage>,
+
+        #[id(2)]
+        counter: std::rc::Rc<u64>,
+
+        #[id(3)]
+        name: std::borrow::Cow<'a, str>,
+
+        #[id(4)]
+        data: std::borrow::Cow<'a, [u8]>,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    struct TestTelemetryMessage {
+        #[id(1)]
+        level: i8,
+
+        #[id(2)]
+        offset: i16,
+
+        #[id(3)]
+        channel: u8,
+
+        #[id(4)]
+        port: u16,
+
+        #[id(5)]
+        count: usize,
+
+        #[id(6)]
+        #[pbtype(sint64)]
+        delta: isize,
+
+        #[id(7)]
+        grade: char,
+
+        #[id(8)]
+        mac: [u8; 6],
+
+        #[id(9)]
+        readings: Vec<u16>,
+
+        #[id(10)]
+        payload: Vec<u8>,
+    }
+
+    /// An IPv4 address with a zero value, so proto3 fields can hold one.
+    #[derive(Clone, Copy, PartialEq)]
+    struct Addr(std::net::Ipv4Addr);
+
+    impl Default for Addr {
+        fn default() -> Self {
+            Addr(std::net::Ipv4Addr::UNSPECIFIED)
+        }
+    }
+
+    impl std::fmt::Debug for Addr {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    mod ipv4_fixed32 {
+        use super::Addr;
+        use std::io::{self, Read, Write};
+        use std::net::Ipv4Addr;
+
+        pub fn encode(addr: &Addr, w: &mut impl Write) -> io::Result<()> {
+            w.write_all(&u32::from(addr.0).to_le_bytes())
+        }
+
+        pub fn decode(r: &mut impl Read) -> Result<Addr, crate::DeserializeError> {
+            let mut buf = [0u8; 4];
+            r.read_exact(&mut buf)?;
+            Ok(Addr(Ipv4Addr::from(u32::from_le_bytes(buf))))
+        }
+
+        pub fn encoded_len(_addr: &Addr) -> usize {
+            4
+        }
+    }
+
+    mod uuid_bytes {
+        use std::io::{self, Read, Write};
+
+        pub fn encode(uuid: &u128, w: &mut impl Write) -> io::Result<()> {
+            w.write_all(&uuid.to_be_bytes())
+        }
+
+        pub fn decode(r: &mut impl Read) -> Result<u128, crate::DeserializeError> {
+            let mut buf = Vec::new();
+            r.read_to_end(&mut buf)?;
+            let buf: [u8; 16] = buf.try_into().map_err(|buf: Vec<u8>| {
+                crate::DeserializeError::TypeError(format!("uuid has {} bytes", buf.len()))
+            })?;
+            Ok(u128::from_be_bytes(buf))
+        }
+
+        pub fn encoded_len(_uuid: &u128) -> usize {
+            16
+        }
+    }
+
+    #[derive(Debug, Serialize, Diff, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestCodecMessage {
+        #[id(1)]
+        #[zombie(with = "uuid_bytes")]
+        id: u128,
+
+        #[id(2)]
+        #[zombie(with = "ipv4_fixed32")]
+        #[pbtype(fixed32)]
+        addr: Addr,
+
+        #[id(3)]
+        #[zombie(with = "ipv4_fixed32")]
+        #[pbtype(fixed32)]
+        gateway: Option<Addr>,
+    }
+
+    fn default_session() -> u32 {
+        7
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct TestTransientMessage {
+        #[id(1)]
+        name: String,
+
+        #[zombie(skip)]
+        cache: std::collections::HashMap<String, u64>,
+
+        #[zombie(skip, default = "default_session")]
+        session: u32,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    #[zombie(debug)]
+    struct TestCredentials {
+        #[id(1)]
+        user: String,
+
+        #[id(2)]
+        #[sensitive]
+        password: String,
+
+        #[id(3)]
+        #[sensitive]
+        token: Option<SubMessage>,
+
+        #[zombie(skip)]
+        attempts: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestUpstream {
+        #[id(1)]
+        #[validate(range(min = -10, max = 10))]
+        weight: i32,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestServerConfig {
+        #[id(1)]
+        #[validate(length(min = 1, max = 16))]
+        name: String,
+
+        #[id(2)]
+        #[validate(range(min = 1, max = 65535))]
+        port: u32,
+
+        #[id(3)]
+        #[validate(length(max = 3), regex = "^[a-z]+$")]
+        tags: Vec<String>,
+
+        #[id(4)]
+        #[validate(regex = r"^[^@\s]+@[^@\s]+\.[a-z]+$")]
+        admin_email: Option<String>,
+
+        #[id(5)]
+        #[validate(required, nested)]
+        upstream: Option<TestUpstream>,
+
+        #[id(6)]
+        #[validate(nested)]
+        replicas: Vec<TestUpstream>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[zombie(syntax = "proto3", validate_on_decode)]
+    struct TestValidatedRequest {
+        #[id(1)]
+        #[validate(range(min = 1))]
+        count: u32,
+
+        #[id(2)]
+        #[validate(nested)]
+        upstream: Option<TestUpstream>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestValidatedWrapper {
+        #[id(1)]
+        request: Option<TestValidatedRequest>,
+    }
+
+    #[derive(Serialize)]
+    struct TestOrderedMessage {
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        string: String,
+
+        #[id(3)]
+        submessage: TestOrderedPairMessage,
+    }
+
+    #[derive(Serialize)]
+    struct TestShuffledMessage {
+        #[id(3)]
+        submessage: TestShuffledPairMessage,
+
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        string: String,
+    }
+
+    #[derive(Serialize)]
+    struct TestOrderedPairMessage {
+        #[id(1)]
+        a: i32,
+
+        #[id(2)]
+        b: i32,
+    }
+
+    #[derive(Serialize)]
+    struct TestShuffledPairMessage {
+        #[id(2)]
+        b: i32,
+
+        #[id(1)]
+        a: i32,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[zombie(builder)]
+    struct TestPairMessage {
+        #[id(1)]
+        a: Option<i32>,
+
+        #[id(2)]
+        b: Option<i32>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[zombie(builder)]
+    struct TestMergeMessage {
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        string: Option<String>,
+
+        #[id(3)]
+        bytes: Vec<u8>,
+
+        #[id(4)]
+        enumeration: TestEnum,
+
+        #[id(5)]
+        repeated: Vec<i32>,
+
+        #[id(6)]
+        submessage: Option<TestPairMessage>,
+
+        #[id(7)]
+        pairs: Vec<TestPairMessage>,
+    }
+
+    #[derive(Serialize, Diff, Deserialize)]
+    #[zombie(syntax = "proto3")]
+    struct TestProto3MergeMessage {
+        #[id(1)]
+        int32: i32,
+
+        #[id(2)]
+        string: String,
+    }
+
     #[test]
     fn test_derive_types() {
         let s = TestMessage {
@@ -320,29 +800,7 @@ mod tests {
             0x01, // repeated
         ];
 
-        // TODO(klimt): Zero this out.
-        let mut actual = TestMessage {
-            int32: 0,
-            int64: 0,
-            uint32: 0,
-            uint64: 0,
-            sint32: 0,
-            sint64: 0,
-            boolean: false,
-            fixed64: 0,
-            sfixed64: 0,
-            fixed32: 0,
-            sfixed32: 0,
-            double: 0.0,
-            float: 0.0,
-            string: "".to_owned(),
-            bytes: vec![],
-            enumeration: TestEnum::VariantTwo,
-            submessage: SubMessage { int32: 0 },
-            repeated: vec![],
-        };
-
-        actual.deserialize(&mut &v[..]).unwrap();
+        let actual = TestMessage::decode_from_slice(&v[..]).unwrap();
 
         let expected = TestMessage {
             int32: 150,
@@ -365,22 +823,1028 @@ mod tests {
             repeated: vec![156, 157, 158],
         };
 
-        assert_eq!(expected.int32, actual.int32);
-        assert_eq!(expected.int64, actual.int64);
-        assert_eq!(expected.uint32, actual.uint32);
-        assert_eq!(expected.uint64, actual.uint64);
-        assert_eq!(expected.sint32, actual.sint32);
-        assert_eq!(expected.sint64, actual.sint64);
-        assert_eq!(expected.double, actual.double);
-        assert_eq!(expected.float, actual.float);
-        assert_eq!(expected.string, actual.string);
-        assert_eq!(expected.bytes, actual.bytes);
-        assert_eq!(expected.submessage.int32, actual.submessage.int32);
-        assert_eq!(expected.repeated, actual.repeated);
+        zombie::assert_message_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_deserialize_missing_required() {
+        let v: Vec<u8> = vec![
+            0x18, 0x01, // answer
+        ];
+        let mut actual = TestProto2Message {
+            int32: None,
+            string: "".to_owned(),
+            answer: 0,
+            label: "".to_owned(),
+            negative: None,
+        };
+        match actual.deserialize(&mut &v[..]) {
+            Err(zombie::DeserializeError::MissingRequired { message, fields }) => {
+                assert_eq!("TestProto2Message", message);
+                assert_eq!(vec!["int32".to_owned(), "string".to_owned()], fields);
+            }
+            _ => panic!("expected missing required error"),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_declared_defaults() {
+        let v: Vec<u8> = vec![
+            0x08, 0x96, 0x01, // int32
+            0x12, 0x02, 0x68, 0x69, // string
+        ];
+        let actual = TestProto2Message::decode_from_slice(&v[..]).unwrap();
+        assert_eq!(Some(150), actual.int32);
+        assert_eq!("hi", actual.string);
+        assert_eq!(42, actual.answer);
+        assert_eq!("x", actual.label);
+        assert_eq!(Some(-7), actual.negative);
+    }
+
+    #[test]
+    fn test_deserialize_defaults_not_applied_when_present() {
+        let v: Vec<u8> = vec![
+            0x08, 0x01, // int32
+            0x12, 0x00, // string
+            0x18, 0x05, // answer
+            0x22, 0x01, 0x79, // label
+        ];
+        let actual = TestProto2Message::decode_from_slice(&v[..]).unwrap();
+        assert_eq!(5, actual.answer);
+        assert_eq!("y", actual.label);
+    }
+
+    #[test]
+    fn test_serialize_missing_required() {
+        let s = TestProto2Message {
+            int32: None,
+            string: "".to_owned(),
+            answer: 42,
+            label: "x".to_owned(),
+            negative: None,
+        };
+        let mut v = Vec::new();
+        let err = s.serialize(&mut v).unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_proto3_omits_default_values() {
+        let s = TestProto3Message {
+            int32: 0,
+            sint64: 0,
+            boolean: false,
+            double: 0.0,
+            string: "".to_owned(),
+            bytes: vec![],
+            enumeration: TestEnum::VariantZero,
+            repeated: vec![],
+            optional: Some(0),
+            submessage: SubMessage { int32: 0 },
+        };
+        let mut v = Vec::new();
+        s.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x48, 0x00, // optional
+                0x52, 0x02, 0x08, 0x00, // submessage
+            ]
+        );
+    }
+
+    #[test]
+    fn test_proto3_emits_set_values() {
+        let s = TestProto3Message {
+            int32: 150,
+            sint64: -1,
+            boolean: true,
+            double: -0.0,
+            string: "a".to_owned(),
+            bytes: vec![1],
+            enumeration: TestEnum::VariantOne,
+            repeated: vec![2],
+            optional: None,
+            submessage: SubMessage { int32: 0 },
+        };
+        let mut v = Vec::new();
+        s.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x08, 0x96, 0x01, // int32
+                0x10, 0x01, // sint64
+                0x18, 0x01, // boolean
+                0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, // double
+                0x2a, 0x01, 0x61, // string
+                0x32, 0x01, 0x01, // bytes
+                0x38, 0x01, // enumeration
+                0x40, 0x02, // repeated
+                0x52, 0x02, 0x08, 0x00, // submessage
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_empty_is_proto_default() {
+        let actual = TestMessage::decode(&[]).unwrap();
+        assert_eq!(0, actual.int32);
+        assert_eq!(0, actual.uint64);
+        assert!(!actual.boolean);
+        assert_eq!(0.0, actual.double);
+        assert_eq!("", actual.string);
+        assert!(actual.bytes.is_empty());
+        assert_eq!(0, actual.submessage.int32);
+        assert!(actual.repeated.is_empty());
+
+        match actual.enumeration {
+            TestEnum::VariantZero => {}
+            _ => panic!("incorrect variant"),
+        }
+    }
+
+    #[test]
+    fn test_proto_default_uses_declared_defaults() {
+        let actual = TestProto2Message::proto_default();
+        assert_eq!(None, actual.int32);
+        assert_eq!("", actual.string);
+        assert_eq!(42, actual.answer);
+        assert_eq!("x", actual.label);
+        assert_eq!(Some(-7), actual.negative);
+    }
+
+    #[test]
+    fn test_merge_from_scalars_last_wins() {
+        let v: Vec<u8> = vec![
+            0x08, 0x01, // int32
+            0x12, 0x01, 0x61, // string
+            0x08, 0x02, // int32
+            0x12, 0x01, 0x62, // string
+        ];
+        let actual = TestMergeMessage::decode_from_slice(&v[..]).unwrap();
+        assert_eq!(2, actual.int32);
+        assert_eq!(Some("b".to_owned()), actual.string);
+    }
+
+    #[test]
+    fn test_merge_from_repeated_appends() {
+        let mut actual = TestMergeMessage::decode_from_slice(&[0x28, 0x01]).unwrap();
+        actual
+            .merge_from(&mut &[0x28, 0x02, 0x28, 0x03][..])
+            .unwrap();
+        assert_eq!(vec![1, 2, 3], actual.repeated);
+    }
+
+    #[test]
+    fn test_merge_from_submessage_merges() {
+        let v: Vec<u8> = vec![
+            0x32, 0x02, 0x08, 0x01, // submessage.a
+            0x32, 0x02, 0x10, 0x02, // submessage.b
+        ];
+        let actual = TestMergeMessage::decode_from_slice(&v[..]).unwrap();
+        let submessage = actual.submessage.unwrap();
+        assert_eq!(Some(1), submessage.a);
+        assert_eq!(Some(2), submessage.b);
+    }
+
+    #[test]
+    fn test_merge_from_keeps_absent_fields() {
+        let mut actual = TestMergeMessage::decode_from_slice(&[0x08, 0x05]).unwrap();
+        actual.merge_from(&mut &[0x12, 0x01, 0x61][..]).unwrap();
+        assert_eq!(5, actual.int32);
+        assert_eq!(Some("a".to_owned()), actual.string);
+    }
+
+    #[test]
+    fn test_merge_message() {
+        let mut actual = TestMergeMessage {
+            int32: 1,
+            string: Some("a".to_owned()),
+            bytes: vec![1, 2],
+            enumeration: TestEnum::VariantOne,
+            repeated: vec![1],
+            submessage: Some(TestPairMessage {
+                a: Some(1),
+                b: None,
+            }),
+            pairs: vec![TestPairMessage {
+                a: Some(1),
+                b: None,
+            }],
+        };
+        let other = TestMergeMessage {
+            int32: 2,
+            string: None,
+            bytes: vec![3],
+            enumeration: TestEnum::VariantTwo,
+            repeated: vec![2, 3],
+            submessage: Some(TestPairMessage {
+                a: None,
+                b: Some(2),
+            }),
+            pairs: vec![TestPairMessage {
+                a: None,
+                b: Some(2),
+            }],
+        };
+        actual.merge(&other);
+
+        assert_eq!(2, actual.int32);
+        assert_eq!(Some("a".to_owned()), actual.string);
+        assert_eq!(vec![3], actual.bytes);
+        assert_eq!(vec![1, 2, 3], actual.repeated);
+        let submessage = actual.submessage.as_ref().unwrap();
+        assert_eq!(Some(1), submessage.a);
+        assert_eq!(Some(2), submessage.b);
+        assert_eq!(2, actual.pairs.len());
+        assert_eq!(Some(2), actual.pairs[1].b);
 
         match actual.enumeration {
             TestEnum::VariantTwo => {}
             _ => panic!("incorrect variant"),
         }
     }
+
+    #[test]
+    fn test_merge_message_into_empty_submessage() {
+        let mut actual = TestMergeMessage::proto_default();
+        let other = TestMergeMessage {
+            submessage: Some(TestPairMessage {
+                a: Some(3),
+                b: None,
+            }),
+            ..TestMergeMessage::proto_default()
+        };
+        actual.merge(&other);
+        assert_eq!(Some(3), actual.submessage.unwrap().a);
+    }
+
+    #[test]
+    fn test_merge_proto3_skips_default_values() {
+        let mut actual = TestProto3MergeMessage {
+            int32: 1,
+            string: "a".to_owned(),
+        };
+        actual.merge(&TestProto3MergeMessage {
+            int32: 0,
+            string: "b".to_owned(),
+        });
+        assert_eq!(1, actual.int32);
+        assert_eq!("b", actual.string);
+    }
+
+    #[test]
+    fn test_clear_message() {
+        let mut actual = TestProto2Message {
+            int32: Some(1),
+            string: "a".to_owned(),
+            answer: 1,
+            label: "a".to_owned(),
+            negative: None,
+        };
+        actual.clear();
+        assert_eq!(None, actual.int32);
+        assert_eq!("", actual.string);
+        assert_eq!(42, actual.answer);
+        assert_eq!("x", actual.label);
+        assert_eq!(Some(-7), actual.negative);
+    }
+
+    #[test]
+    fn test_field_number_order() {
+        let ordered = TestOrderedMessage {
+            int32: 150,
+            string: "hi".to_owned(),
+            submessage: TestOrderedPairMessage { a: 1, b: 2 },
+        };
+        let shuffled = TestShuffledMessage {
+            submessage: TestShuffledPairMessage { b: 2, a: 1 },
+            int32: 150,
+            string: "hi".to_owned(),
+        };
+
+        let mut a = Vec::new();
+        ordered.serialize(&mut a).unwrap();
+        let mut b = Vec::new();
+        shuffled.serialize(&mut b).unwrap();
+        assert_eq!(
+            a,
+            vec![
+                0x08, 0x96, 0x01, // int32
+                0x12, 0x02, b'h', b'i', // string
+                0x1a, 0x04, 0x08, 0x01, 0x10, 0x02, // submessage
+            ]
+        );
+        assert_eq!(a, b);
+
+        // Field-number order doesn't depend on the option.
+        let mut c = Vec::new();
+        shuffled.serialize(&mut c).unwrap();
+        assert_eq!(a, c);
+    }
+
+    fn test_message(int32: i32, repeated: Vec<u32>) -> TestMessage {
+        TestMessage {
+            submessage: SubMessage { int32 },
+            repeated,
+            ..TestMessage::proto_default()
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_paths() {
+        let a = test_message(0, vec![156, 157, 158]);
+        let b = test_message(150, vec![156, 157, 159, 160]);
+        let diff = zombie::Diff::diff(&a, &b);
+        assert_eq!(
+            diff.to_string(),
+            "submessage.int32: 0 -> 150\nrepeated[2]: 158 -> 159\n+ repeated[3]: 160\n"
+        );
+        assert!(zombie::Diff::diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_diff_optional_fields() {
+        let a = TestOptionalMessage::proto_default();
+        let mut b = TestOptionalMessage::proto_default();
+        b.int32 = Some(150);
+        b.submessage = Some(SubMessage { int32: 1 });
+        let diff = zombie::Diff::diff(&a, &b);
+        assert_eq!(
+            diff.to_string(),
+            "+ int32: 150\n+ submessage: SubMessage { int32: 1 }\n"
+        );
+        assert_eq!(
+            zombie::Diff::diff(&b, &a).changes[0],
+            zombie::FieldChange::Removed {
+                path: "int32".to_owned(),
+                value: "150".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "submessage.int32: 0 -> 150")]
+    fn test_assert_message_eq_prints_diff() {
+        zombie::assert_message_eq!(test_message(0, vec![]), test_message(150, vec![]));
+    }
+
+    #[test]
+    fn test_decode_error_on_malformed_lengths() {
+        let v = [
+            0x08, 0x96, 0x01, // int32
+            0x10, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01, // int64
+        ];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert_eq!(
+            "failed to decode TestMessage.int64 (field 2, wire type VarInt) at byte 4: \
+             io error: varint longer than 10 bytes",
+            err.to_string()
+        );
+
+        let v = [
+            0x72, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, b'a', // string
+        ];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert_eq!(
+            "failed to decode TestMessage.string (field 14, wire type Len) at byte 1: \
+             io error: expected 18446744073709551615 bytes, got 1",
+            err.to_string()
+        );
+
+        // Unknown fields are skipped through the same reads.
+        let v = [0xfa, 0x01, 0xff, 0xff, 0xff, 0xff, 0x0f];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert!(err
+            .to_string()
+            .starts_with("failed to decode TestMessage at byte 2"));
+    }
+
+    #[test]
+    fn test_decode_error_locates_nested_field() {
+        let v = [
+            0x08, 0x96, 0x01, // int32
+            0x9a, 0x01, 0x02, 0x0a, 0x00, // submessage with int32 as a string
+        ];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        match &err {
+            zombie::DeserializeError::InMessage {
+                message,
+                path,
+                field,
+                wiretype,
+                offset,
+                ..
+            } => {
+                assert_eq!("TestMessage", message);
+                assert_eq!("submessage.int32", path);
+                assert_eq!(Some(1), *field);
+                assert_eq!(Some(zombie::WireType::Len), *wiretype);
+                assert_eq!(7, *offset);
+            }
+            _ => panic!("unexpected error: {:?}", err),
+        }
+        assert_eq!(
+            "failed to decode TestMessage.submessage.int32 (field 1, wire type Len) at byte 7: \
+             type error: `invalid wiretype for i32: Len`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_decode_error_locates_repeated_element() {
+        let v = [
+            0xa0, 0x01, 0x9c, 0x01, // repeated
+            0xa2, 0x01, 0x00, // repeated as a string
+        ];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert_eq!(
+            "failed to decode TestMessage.repeated[1] (field 20, wire type Len) at byte 6: \
+             type error: `invalid wiretype for u32: Len`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_decode_error_locates_bad_tag() {
+        let v = [0x08, 0x01, 0x0f];
+        let err = TestMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert_eq!(
+            "failed to decode TestMessage at byte 2: io error: invalid wiretype: 7",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_reserved_message_round_trip() {
+        let s = TestReservedMessage { a: 1, b: 2 };
+        let mut v = Vec::new();
+        s.serialize(&mut v).unwrap();
+        assert_eq!(v, vec![0x08, 0x01, 0x60, 0x02]);
+        let d = TestReservedMessage::decode_from_slice(&v).unwrap();
+        assert_eq!((1, 2), (d.a, d.b));
+    }
+
+    #[test]
+    fn test_generic_envelope() {
+        let envelope = TestEnvelope {
+            kind: "sub".to_owned(),
+            payload: Some(SubMessage { int32: 150 }),
+        };
+        let mut v = Vec::new();
+        envelope.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x0a, 0x03, b's', b'u', b'b', // kind
+                0x12, 0x03, 0x08, 0x96, 0x01, // payload
+            ]
+        );
+        let decoded = TestEnvelope::<SubMessage>::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(envelope, decoded);
+
+        let envelope = TestEnvelope {
+            kind: "int".to_owned(),
+            payload: Some(150i64),
+        };
+        let mut v = Vec::new();
+        envelope.serialize(&mut v).unwrap();
+        let decoded = TestEnvelope::<i64>::decode_from_slice(&v).unwrap();
+        assert_eq!(Some(150), decoded.payload);
+    }
+
+    #[test]
+    fn test_generic_where_clause() {
+        let batch = TestBatch {
+            items: vec![1i32, 2],
+        };
+        let mut v = Vec::new();
+        batch.serialize(&mut v).unwrap();
+        assert_eq!(v, vec![0x08, 0x01, 0x08, 0x02]);
+        let decoded = TestBatch::<i32>::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(batch, decoded);
+    }
+
+    #[test]
+    fn test_borrowed_view() {
+        let name = String::from("hi");
+        let data = [1u8, 2];
+        let view = TestView {
+            name: &name,
+            data: &data,
+            tags: vec!["a", ""],
+        };
+        let mut v = Vec::new();
+        view.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x0a, 0x02, b'h', b'i', // name
+                0x12, 0x02, 0x01, 0x02, // data
+                0x1a, 0x01, b'a', 0x1a, 0x00, // tags
+            ]
+        );
+
+        let empty = TestView {
+            name: "",
+            data: &[],
+            tags: vec![],
+        };
+        let mut v = Vec::new();
+        empty.serialize(&mut v).unwrap();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn test_recursive_box() {
+        let tree = TestTreeNode {
+            value: 1,
+            left: Some(Box::new(TestTreeNode {
+                value: 2,
+                left: None,
+                children: vec![],
+            })),
+            children: vec![TestTreeNode {
+                value: 3,
+                left: None,
+                children: vec![],
+            }],
+        };
+        let mut v = Vec::new();
+        tree.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x08, 0x01, // value
+                0x12, 0x02, 0x08, 0x02, // left
+                0x1a, 0x02, 0x08, 0x03, // children
+            ]
+        );
+        let decoded = TestTreeNode::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(tree, decoded);
+    }
+
+    /// `depth` levels of `left`, each wrapping the next.
+    fn nested_tree(depth: usize) -> Vec<u8> {
+        // Headers from the innermost out, so that each length is known when written.
+        let mut headers = Vec::new();
+        let mut len = 0;
+        for _ in 0..depth {
+            let mut header = vec![0x12];
+            zombie::write_uvarint(&mut header, len as u64).unwrap();
+            len += header.len();
+            headers.push(header);
+        }
+        headers.into_iter().rev().flatten().collect()
+    }
+
+    fn innermost(error: &zombie::DeserializeError) -> &zombie::DeserializeError {
+        match error {
+            zombie::DeserializeError::InMessage { source, .. } => innermost(source),
+            error => error,
+        }
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        assert!(TestTreeNode::decode_from_slice(&nested_tree(zombie::RECURSION_LIMIT)).is_ok());
+        for depth in [zombie::RECURSION_LIMIT + 1, 50_000] {
+            let Err(error) = TestTreeNode::decode_from_slice(&nested_tree(depth)) else {
+                panic!("decoded {} levels", depth);
+            };
+            assert!(matches!(
+                innermost(&error),
+                zombie::DeserializeError::RecursionLimitExceeded
+            ));
+        }
+        // The guards are released again, on success and on error.
+        assert!(TestTreeNode::decode_from_slice(&nested_tree(zombie::RECURSION_LIMIT)).is_ok());
+    }
+
+    #[test]
+    fn test_pointer_fields() {
+        let shared = std::sync::Arc::new(SubMessage { int32: 150 });
+        let data = [1u8, 2];
+        let message = TestPointerMessage {
+            shared: shared.clone(),
+            counter: std::rc::Rc::new(7),
+            name: "hi".into(),
+            data: data[..].into(),
+        };
+        let mut v = Vec::new();
+        message.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x0a, 0x03, 0x08, 0x96, 0x01, // shared
+                0x10, 0x07, // counter
+                0x1a, 0x02, b'h', b'i', // name
+                0x22, 0x02, 0x01, 0x02, // data
+            ]
+        );
+        let decoded = TestPointerMessage::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(message, decoded);
+        assert!(matches!(decoded.name, std::borrow::Cow::Owned(_)));
+        assert_eq!(2, std::sync::Arc::strong_count(&shared));
+    }
+
+    #[test]
+    fn test_widened_integers() {
+        let message = TestTelemetryMessage {
+            level: -1,
+            offset: 300,
+            channel: 200,
+            port: 8080,
+            count: 3,
+            delta: -2,
+            grade: 'A',
+            mac: [0, 1, 2, 3, 4, 5],
+            readings: vec![1, 2],
+            payload: vec![7, 8],
+        };
+        let mut v = Vec::new();
+        message.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x08, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, // level
+                0x10, 0xac, 0x02, // offset
+                0x18, 0xc8, 0x01, // channel
+                0x20, 0x90, 0x3f, // port
+                0x28, 0x03, // count
+                0x30, 0x03, // delta
+                0x38, 0x41, // grade
+                0x42, 0x06, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, // mac
+                0x48, 0x01, 0x48, 0x02, // readings
+                0x52, 0x02, 0x07, 0x08, // payload
+            ]
+        );
+        let decoded = TestTelemetryMessage::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(message, decoded);
+    }
+
+    #[test]
+    fn test_narrowing_out_of_range() {
+        let v = [0x18, 0x80, 0x02]; // channel = 256
+        let err = TestTelemetryMessage::decode_from_slice(&v[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            "failed to decode TestTelemetryMessage.channel (field 3, wire type VarInt) at byte 1: \
+             value 256 out of range for u8",
+            err.to_string()
+        );
+
+        let v = [0x38, 0x80, 0xb0, 0x03]; // grade = 0xd800, a surrogate
+        let err = TestTelemetryMessage::decode_from_slice(&v[..])
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .ends_with("value 55296 out of range for char"));
+    }
+
+    #[test]
+    fn test_fixed_size_bytes_length() {
+        let v = [0x42, 0x02, 0x00, 0x01];
+        let err = TestTelemetryMessage::decode_from_slice(&v[..])
+            .err()
+            .unwrap();
+        assert!(err
+            .to_string()
+            .ends_with("expected 6 bytes for [u8; 6], got 2"));
+    }
+
+    #[test]
+    fn test_with_codec() {
+        let message = TestCodecMessage {
+            id: 0x0102,
+            addr: Addr(std::net::Ipv4Addr::new(10, 0, 0, 1)),
+            gateway: None,
+        };
+        let mut v = Vec::new();
+        message.serialize(&mut v).unwrap();
+        let mut expected = vec![0x0a, 0x10];
+        expected.extend_from_slice(&0x0102u128.to_be_bytes());
+        expected.extend_from_slice(&[0x15, 0x01, 0x00, 0x00, 0x0a]);
+        assert_eq!(v, expected);
+
+        let decoded = TestCodecMessage::decode_from_slice(&v).unwrap();
+        zombie::assert_message_eq!(message, decoded);
+
+        // An all-zero address is the fixed32 default, but sixteen zero bytes aren't empty.
+        let zero = TestCodecMessage {
+            id: 0,
+            addr: Addr::default(),
+            gateway: None,
+        };
+        let mut v = Vec::new();
+        zero.serialize(&mut v).unwrap();
+        let mut expected = vec![0x0a, 0x10];
+        expected.extend_from_slice(&[0; 16]);
+        assert_eq!(v, expected);
+
+        let empty = TestCodecMessage::proto_default();
+        assert_eq!(Addr::default(), empty.addr);
+        assert_eq!(
+            "addr: 0.0.0.0 -> 10.0.0.1\n",
+            zombie::Diff::diff(&empty, &decoded)
+                .changes
+                .iter()
+                .filter(|change| change.to_string().starts_with("addr"))
+                .map(|change| format!("{}\n", change))
+                .collect::<String>()
+        );
+
+        let mut merged = decoded;
+        let gateway = TestCodecMessage {
+            gateway: Some(Addr(std::net::Ipv4Addr::new(10, 0, 0, 254))),
+            ..TestCodecMessage::proto_default()
+        };
+        zombie::MergeField::merge_field(&mut merged, &gateway);
+        assert_eq!(gateway.gateway, merged.gateway);
+    }
+
+    #[test]
+    fn test_with_codec_wire_type_mismatch() {
+        let v = [0x10, 0x01]; // addr as a varint
+        let err = TestCodecMessage::decode_from_slice(&v[..]).err().unwrap();
+        assert!(err
+            .to_string()
+            .ends_with("type error: `invalid wiretype for fixed32: VarInt`"));
+    }
+
+    #[test]
+    fn test_skipped_fields() {
+        let mut message = TestTransientMessage {
+            name: "a".to_owned(),
+            cache: std::collections::HashMap::new(),
+            session: 42,
+        };
+        message.cache.insert("a".to_owned(), 1);
+        let mut v = Vec::new();
+        message.serialize(&mut v).unwrap();
+        assert_eq!(v, vec![0x0a, 0x01, b'a']);
+
+        let decoded = TestTransientMessage::decode_from_slice(&v).unwrap();
+        assert_eq!("a", decoded.name);
+        assert!(decoded.cache.is_empty());
+        assert_eq!(7, decoded.session);
+    }
+
+    #[test]
+    fn test_message_api() {
+        let mut message = TestProto3MergeMessage {
+            int32: 150,
+            string: "a".to_owned(),
+        };
+        let v = message.encode_to_vec().unwrap();
+        assert_eq!(v, vec![0x08, 0x96, 0x01, 0x12, 0x01, b'a']);
+        assert_eq!(v.len(), message.encoded_len().unwrap());
+
+        let mut w = Vec::new();
+        message.encode(&mut w).unwrap();
+        assert_eq!(v, w);
+
+        let decoded = TestProto3MergeMessage::decode(&v).unwrap();
+        zombie::assert_message_eq!(message, decoded);
+
+        message.merge(&TestProto3MergeMessage {
+            int32: 2,
+            string: String::new(),
+        });
+        assert_eq!(2, message.int32);
+        assert_eq!("a", message.string);
+        message.clear();
+        assert_eq!(0, message.encoded_len().unwrap());
+    }
+
+    #[test]
+    fn test_message_missing_required() {
+        let message = TestProto2Message::proto_default();
+        assert!(message.encode_to_vec().is_err());
+        assert!(message.encoded_len().is_err());
+    }
+
+    #[test]
+    fn test_builder() {
+        let message = TestMessage::builder()
+            .int32(150)
+            .string("hello")
+            .submessage(|s| s.int32(1))
+            .push_repeated(1u32)
+            .extend_repeated([2, 3])
+            .bytes(&b"ab"[..])
+            .with_enumeration(TestEnum::VariantOne)
+            .build();
+        assert_eq!(150, message.int32);
+        assert_eq!("hello", message.string);
+        assert_eq!(1, message.submessage.int32);
+        assert_eq!(vec![1, 2, 3], message.repeated);
+        assert_eq!(b"ab".to_vec(), message.bytes);
+        assert_eq!(0, message.int64);
+
+        let decoded = TestMessage::decode(&message.encode_to_vec().unwrap()).unwrap();
+        zombie::assert_message_eq!(message, decoded);
+    }
+
+    #[test]
+    fn test_builder_optional_fields() {
+        let mut message = TestMergeMessage::builder()
+            .string("a")
+            .submessage(|p| p.with_a(Some(1)))
+            .build();
+        assert!(message.has_string());
+        assert_eq!(Some(1), message.submessage.as_ref().unwrap().a);
+
+        message.clear_string();
+        assert!(!message.has_string());
+        message.set_string("b");
+        assert_eq!(Some("b".to_owned()), message.string);
+
+        let envelope = TestEnvelope::<SubMessage>::builder()
+            .kind("sub")
+            .payload(|s| s.int32(3))
+            .build();
+        assert_eq!(3, envelope.payload.unwrap().int32);
+    }
+
+    #[test]
+    fn test_sensitive_fields() {
+        let message = TestCredentials {
+            user: "alice".to_owned(),
+            password: "hunter2".to_owned(),
+            token: Some(SubMessage { int32: 9 }),
+            attempts: 1,
+        };
+        assert_eq!(
+            r#"TestCredentials { user: "alice", password: <redacted>, token: <redacted>, .. }"#,
+            format!("{:?}", message)
+        );
+        assert_eq!(
+            r#"TestCredentials { user: "alice", password: "hunter2", token: Some(SubMessage { int32: 9 }), .. }"#,
+            zombie::unredacted(|| format!("{:?}", message))
+        );
+        assert!(zombie::redacting());
+
+        let other = TestCredentials {
+            user: "bob".to_owned(),
+            password: "swordfish".to_owned(),
+            token: None,
+            attempts: 0,
+        };
+        assert_eq!(
+            "user: \"alice\" -> \"bob\"\npassword: <redacted> -> <redacted>\n- token: <redacted>\n",
+            zombie::Diff::diff(&message, &other).to_string()
+        );
+        let diff = zombie::unredacted(|| zombie::Diff::diff(&message, &other));
+        assert_eq!(
+            "- token: SubMessage { int32: 9 }",
+            diff.changes[2].to_string()
+        );
+        assert_eq!(
+            "TestCredentials { user: \"alice\", password: <redacted>, token: <redacted> }",
+            zombie::Diff::describe(&message)
+        );
+
+        // The skipped field behind the `..` never reaches the wire.
+        let decoded = TestCredentials::decode(&message.encode_to_vec().unwrap()).unwrap();
+        assert_eq!("hunter2", decoded.password);
+        assert_eq!(0, decoded.attempts);
+
+        let bytes = message.encode_to_vec().unwrap();
+        let descriptor = TestCredentials::descriptor().unwrap();
+        assert_eq!(
+            zombie::wire::annotate(&bytes, Some(&descriptor)),
+            "\
+000000  0a 05                    1 user: len 5, string
+000002    61 6c 69 63 65           \"alice\"
+000007  12 07                    2 password: len 7, <redacted>
+000010  1a 02                    3 token: len 2, <redacted>
+"
+        );
+        assert_eq!(
+            zombie::unredacted(|| zombie::wire::annotate(&bytes, Some(&descriptor))),
+            "\
+000000  0a 05                    1 user: len 5, string
+000002    61 6c 69 63 65           \"alice\"
+000007  12 07                    2 password: len 7, string
+000009    68 75 6e 74 65 72 32     \"hunter2\"
+000010  1a 02                    3 token: len 2, message SubMessage
+000012    08 09                    1 int32: varint 9
+"
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let tree = TestTreeNode {
+            value: -2,
+            left: Some(Box::new(TestTreeNode {
+                value: 1,
+                left: None,
+                children: vec![],
+            })),
+            children: vec![],
+        };
+        let mut bytes = tree.encode_to_vec().unwrap();
+        bytes.extend([0xff, 0xff]);
+        let descriptor = TestTreeNode::descriptor().unwrap();
+        assert_eq!(
+            zombie::wire::annotate(&bytes, Some(&descriptor)),
+            "\
+000000  08 fe ff ff ff ff ff ff  1 value: varint 18446744073709551614 = -2
+000008  ff ff 01
+00000b  12 02                    2 left: len 2, message TestTreeNode
+00000d    08 01                    1 value: varint 1
+00000f  ff ff                    trailing garbage: 2 bytes
+"
+        );
+
+        assert_eq!(
+            zombie::wire::annotate(&bytes[0x0b..0x0e], Some(&descriptor)),
+            "000000  12 02 08                 2 left: len 2, truncated: 1 bytes left\n"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = TestServerConfig {
+            name: "edge".to_owned(),
+            port: 443,
+            tags: vec!["eu".to_owned(), "prod".to_owned()],
+            admin_email: Some("ops@example.com".to_owned()),
+            upstream: Some(TestUpstream { weight: 1 }),
+            replicas: vec![TestUpstream { weight: -10 }],
+        };
+        assert!(zombie::Validate::validate(&config).is_ok());
+
+        config.name.clear();
+        config.port = 70000;
+        config.tags = vec![
+            "a".to_owned(),
+            "B".to_owned(),
+            "c".to_owned(),
+            "d".to_owned(),
+        ];
+        config.admin_email = Some("not an email".to_owned());
+        config.upstream = None;
+        config.replicas.push(TestUpstream { weight: 11 });
+        let errors = zombie::Validate::validate(&config).unwrap_err();
+        assert_eq!(
+            vec![
+                "name: length must be at least 1",
+                "port: must be at most 65535",
+                "tags: length must be at most 3",
+                "tags[1]: must match `^[a-z]+$`",
+                "admin_email: must match `^[^@\\s]+@[^@\\s]+\\.[a-z]+$`",
+                "upstream: is required",
+                "replicas[1].weight: must be at most 10",
+            ],
+            errors
+                .violations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_validate_on_decode() {
+        let request = TestValidatedRequest {
+            count: 2,
+            upstream: Some(TestUpstream { weight: 3 }),
+        };
+        let decoded = TestValidatedRequest::decode(&request.encode_to_vec().unwrap()).unwrap();
+        assert_eq!(2, decoded.count);
+
+        let request = TestValidatedRequest {
+            count: 0,
+            upstream: None,
+        };
+        let error = TestValidatedRequest::decode(&request.encode_to_vec().unwrap()).unwrap_err();
+        assert_eq!(
+            "invalid message: count: must be at least 1",
+            error.to_string()
+        );
+        assert!(matches!(error, zombie::DeserializeError::Invalid(_)));
+
+        // Nested rules reach into submessages that don't validate on decode themselves.
+        let request = TestValidatedRequest {
+            count: 1,
+            upstream: Some(TestUpstream { weight: 20 }),
+        };
+        let error = TestValidatedRequest::decode(&request.encode_to_vec().unwrap()).unwrap_err();
+        assert_eq!(
+            "invalid message: upstream.weight: must be at most 10",
+            error.to_string()
+        );
+
+        // Only whole messages are checked, not what a merge or submessage adds.
+        let mut request = TestValidatedRequest::decode(&[0x08, 0x01]).unwrap();
+        request.merge_from(&mut &[0x12, 0x00][..]).unwrap();
+        let wrapper = TestValidatedWrapper::decode(&[0x0a, 0x00]).unwrap();
+        assert_eq!(Some(0), wrapper.request.map(|request| request.count));
+    }
 }
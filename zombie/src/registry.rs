@@ -0,0 +1,119 @@
+use crate::{
+    wkt::{type_name, Any},
+    Deserialize, DeserializeError, Name, ProtoDefault,
+};
+
+use std::{collections::HashMap, fmt::Debug};
+
+/// A decoded message whose concrete type is only known at runtime. It can be
+/// routed by `type_url`, downcast with `as_any`, and printed with `Debug`; the
+/// crate has no text or JSON printers to render it with.
+pub trait AnyMessage: Debug {
+    fn type_url(&self) -> String;
+    fn as_any(&self) -> &dyn std::any::Any;
+}
+
+impl<T: Debug + Name + 'static> AnyMessage for T {
+    fn type_url(&self) -> String {
+        T::type_url()
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+type DecodeFn = fn(&[u8]) -> Result<Box<dyn AnyMessage>, DeserializeError>;
+
+fn decode_boxed<T>(buf: &[u8]) -> Result<Box<dyn AnyMessage>, DeserializeError>
+where
+    T: Deserialize + ProtoDefault + Name + Debug + 'static,
+{
+    Ok(Box::new(T::decode_from_slice(buf)?))
+}
+
+/// Maps type URLs to decoders, so `Any` payloads can be decoded and routed without
+/// knowing their type at compile time. It holds decoders only, not descriptors, so
+/// it doesn't help render a payload beyond what `Debug` prints.
+#[derive(Default)]
+pub struct TypeRegistry {
+    // Type URLs may use any host, so entries are keyed by the full message name.
+    decoders: HashMap<String, DecodeFn>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> TypeRegistry {
+        TypeRegistry::default()
+    }
+
+    pub fn register<T>(&mut self)
+    where
+        T: Deserialize + ProtoDefault + Name + Debug + 'static,
+    {
+        self.decoders.insert(T::full_name(), decode_boxed::<T>);
+    }
+
+    pub fn contains(&self, type_url: &str) -> bool {
+        self.decoders.contains_key(type_name(type_url))
+    }
+
+    pub fn decode(&self, any: &Any) -> Result<Box<dyn AnyMessage>, DeserializeError> {
+        let decode = self.decoders.get(any.type_name()).ok_or_else(|| {
+            DeserializeError::TypeError(format!("unknown type: {}", any.type_url))
+        })?;
+        decode(&any.value[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wkt::{Duration, StringValue, Timestamp};
+
+    #[test]
+    fn decode_registered_type() {
+        let mut registry = TypeRegistry::new();
+        registry.register::<Timestamp>();
+        registry.register::<StringValue>();
+
+        let any = Any::pack(&Timestamp {
+            seconds: 1,
+            nanos: 2,
+        })
+        .unwrap();
+        assert!(registry.contains(&any.type_url));
+
+        let message = registry.decode(&any).unwrap();
+        assert_eq!(any.type_url, message.type_url());
+        assert_eq!(
+            "Timestamp { seconds: 1, nanos: 2 }",
+            format!("{:?}", message)
+        );
+        assert_eq!(
+            Some(&Timestamp {
+                seconds: 1,
+                nanos: 2
+            }),
+            message.as_any().downcast_ref::<Timestamp>()
+        );
+    }
+
+    #[test]
+    fn decode_unregistered_type() {
+        let registry = TypeRegistry::new();
+        let any = Any::pack(&Duration {
+            seconds: 1,
+            nanos: 0,
+        })
+        .unwrap();
+        assert!(!registry.contains(&any.type_url));
+        assert!(registry.decode(&any).is_err());
+    }
+
+    #[test]
+    fn custom_type_url_host() {
+        let mut registry = TypeRegistry::new();
+        registry.register::<StringValue>();
+        assert!(registry.contains("example.com/types/google.protobuf.StringValue"));
+    }
+}
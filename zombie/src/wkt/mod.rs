@@ -0,0 +1,320 @@
+//! The `google.protobuf` well-known types.
+
+mod value;
+
+pub use value::{ListValue, Struct, Value};
+
+use crate::{Deserialize, DeserializeError, DeserializeField, Diff, Name, ProtoDefault, Serialize};
+
+use std::{io, time::SystemTime};
+use zombie_core::time::{system_time_from_parts, system_time_to_parts};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct Timestamp {
+    #[id(1)]
+    pub seconds: i64,
+
+    #[id(2)]
+    pub nanos: i32,
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(time: SystemTime) -> Self {
+        let (seconds, nanos) = system_time_to_parts(&time);
+        Timestamp { seconds, nanos }
+    }
+}
+
+/// Fails, giving the timestamp back, if it is outside the Timestamp range or can't
+/// be represented as a `SystemTime` on this platform.
+impl TryFrom<Timestamp> for SystemTime {
+    type Error = Timestamp;
+
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        system_time_from_parts(timestamp.seconds, timestamp.nanos).map_err(|_| timestamp)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct Duration {
+    #[id(1)]
+    pub seconds: i64,
+
+    #[id(2)]
+    pub nanos: i32,
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Duration {
+            seconds: duration.as_secs() as i64,
+            nanos: duration.subsec_nanos() as i32,
+        }
+    }
+}
+
+impl TryFrom<Duration> for std::time::Duration {
+    type Error = Duration;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        if duration.seconds < 0 || duration.nanos < 0 {
+            Err(duration)
+        } else {
+            Ok(std::time::Duration::new(
+                duration.seconds as u64,
+                duration.nanos as u32,
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct Empty {}
+
+/// An arbitrary message along with the URL identifying its type.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct Any {
+    #[id(1)]
+    pub type_url: String,
+
+    #[id(2)]
+    pub value: Vec<u8>,
+}
+
+impl Any {
+    pub fn pack<T: Serialize + Name>(message: &T) -> io::Result<Any> {
+        let mut value = Vec::new();
+        message.serialize(&mut value)?;
+        Ok(Any {
+            type_url: T::type_url(),
+            value,
+        })
+    }
+
+    pub fn unpack<T: Deserialize + ProtoDefault + Name>(&self) -> Result<T, DeserializeError> {
+        if !self.is::<T>() {
+            return Err(DeserializeError::TypeError(format!(
+                "expected {} but Any contains {}",
+                T::full_name(),
+                self.type_name()
+            )));
+        }
+        T::decode_from_slice(&self.value[..])
+    }
+
+    pub fn is<T: Name>(&self) -> bool {
+        self.type_name() == T::full_name()
+    }
+
+    pub fn type_name(&self) -> &str {
+        type_name(&self.type_url)
+    }
+}
+
+/// The fully-qualified message name in a type URL, which is everything after the last `/`.
+pub(crate) fn type_name(type_url: &str) -> &str {
+    match type_url.rfind('/') {
+        Some(i) => &type_url[i + 1..],
+        None => type_url,
+    }
+}
+
+/// A set of field paths, such as `submessage.int32`, naming part of a message.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct FieldMask {
+    #[id(1)]
+    pub paths: Vec<String>,
+}
+
+impl FieldMask {
+    pub fn new<I, S>(paths: I) -> FieldMask
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        FieldMask {
+            paths: paths.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+macro_rules! wrapper {
+    ($name:ident, $ty:ty) => {
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Diff, Name)]
+        #[zombie(syntax = "proto3", package = "google.protobuf")]
+        pub struct $name {
+            #[id(1)]
+            pub value: $ty,
+        }
+
+        impl From<$ty> for $name {
+            fn from(value: $ty) -> Self {
+                $name { value }
+            }
+        }
+
+        impl From<$name> for $ty {
+            fn from(wrapper: $name) -> Self {
+                wrapper.value
+            }
+        }
+    };
+}
+
+wrapper!(DoubleValue, f64);
+wrapper!(FloatValue, f32);
+wrapper!(Int64Value, i64);
+wrapper!(UInt64Value, u64);
+wrapper!(Int32Value, i32);
+wrapper!(UInt32Value, u32);
+wrapper!(BoolValue, bool);
+wrapper!(StringValue, String);
+wrapper!(BytesValue, Vec<u8>);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[derive(Serialize, Deserialize)]
+    struct Event {
+        #[id(1)]
+        at: SystemTime,
+
+        #[id(2)]
+        took: std::time::Duration,
+
+        #[id(3)]
+        retries: Option<Int32Value>,
+    }
+
+    #[test]
+    fn any_pack_unpack() {
+        let timestamp = Timestamp {
+            seconds: 150,
+            nanos: 0,
+        };
+        let any = Any::pack(&timestamp).unwrap();
+        assert_eq!(
+            "type.googleapis.com/google.protobuf.Timestamp",
+            any.type_url
+        );
+        assert_eq!(vec![0x08, 0x96, 0x01], any.value);
+        assert!(any.is::<Timestamp>());
+        assert!(!any.is::<Duration>());
+        assert_eq!(timestamp, any.unpack::<Timestamp>().unwrap());
+        assert!(any.unpack::<Duration>().is_err());
+    }
+
+    #[test]
+    fn any_serialize() {
+        let any = Any::pack(&Int32Value::from(1)).unwrap();
+        let mut v = Vec::new();
+        any.serialize(&mut v).unwrap();
+        let mut expected = vec![0x0a, 0x2e];
+        expected.extend_from_slice(b"type.googleapis.com/google.protobuf.Int32Value");
+        expected.extend_from_slice(&[0x12, 0x02, 0x08, 0x01]);
+        assert_eq!(expected, v);
+        assert_eq!(any, Any::decode_from_slice(&v[..]).unwrap());
+    }
+
+    #[test]
+    fn timestamp_serialize() {
+        let timestamp = Timestamp {
+            seconds: 150,
+            nanos: 1,
+        };
+        let mut v = Vec::new();
+        timestamp.serialize(&mut v).unwrap();
+        assert_eq!(v, vec![0x08, 0x96, 0x01, 0x10, 0x01]);
+    }
+
+    #[test]
+    fn system_time_matches_timestamp() {
+        let time = UNIX_EPOCH + std::time::Duration::new(150, 1);
+        let mut expected = Vec::new();
+        Timestamp::from(time)
+            .serialize_field(1, crate::ProtoType::Message, &mut expected)
+            .unwrap();
+        let mut actual = Vec::new();
+        time.serialize_field(1, crate::ProtoType::Message, &mut actual)
+            .unwrap();
+        assert_eq!(expected, actual);
+        assert_eq!(time, SystemTime::try_from(Timestamp::from(time)).unwrap());
+        let timestamp = Timestamp {
+            seconds: i64::MIN,
+            nanos: 0,
+        };
+        assert_eq!(Err(timestamp), SystemTime::try_from(timestamp));
+    }
+
+    #[test]
+    fn duration_conversions() {
+        let duration = Duration::from(std::time::Duration::new(3, 5));
+        assert_eq!(
+            Duration {
+                seconds: 3,
+                nanos: 5
+            },
+            duration
+        );
+        assert_eq!(
+            Ok(std::time::Duration::new(3, 5)),
+            std::time::Duration::try_from(duration)
+        );
+        assert!(std::time::Duration::try_from(Duration {
+            seconds: -1,
+            nanos: 0
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn time_fields_round_trip() {
+        let event = Event {
+            at: UNIX_EPOCH + std::time::Duration::new(150, 0),
+            took: std::time::Duration::from_millis(1500),
+            retries: Some(Int32Value::from(2)),
+        };
+        let mut v = Vec::new();
+        event.serialize(&mut v).unwrap();
+        assert_eq!(
+            v,
+            vec![
+                0x0a, 0x03, 0x08, 0x96, 0x01, // at
+                0x12, 0x08, 0x08, 0x01, 0x10, 0x80, 0xca, 0xb5, 0xee, 0x01, // took
+                0x1a, 0x02, 0x08, 0x02, // retries
+            ]
+        );
+
+        let actual = Event::decode_from_slice(&v[..]).unwrap();
+        assert_eq!(event.at, actual.at);
+        assert_eq!(event.took, actual.took);
+        assert_eq!(event.retries, actual.retries);
+    }
+
+    #[test]
+    fn wrapper_omits_default_value() {
+        let mut v = Vec::new();
+        StringValue::from(String::new()).serialize(&mut v).unwrap();
+        assert!(v.is_empty());
+
+        let actual = BoolValue::decode_from_slice(&[0x08, 0x01]).unwrap();
+        assert!(bool::from(actual));
+    }
+
+    #[test]
+    fn empty_skips_unknown_fields() {
+        let v = vec![
+            0x08, 0x96, 0x01, // varint
+            0x12, 0x01, 0x61, // len
+            0x1d, 0x00, 0x00, 0x00, 0x00, // i32
+        ];
+        assert_eq!(Empty {}, Empty::decode_from_slice(&v[..]).unwrap());
+    }
+}
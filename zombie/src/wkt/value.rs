@@ -0,0 +1,624 @@
+//! `google.protobuf.Struct`, `Value` and `ListValue`, for free-form JSON-like data.
+
+use crate::{
+    field_path, read_tag, skip_field, uvarint_len, write_tag, write_uvarint, Deserialize,
+    DeserializeError, DeserializeField, Diff, MergeField, MessageDiff, Name, ProtoDefault,
+    ProtoType, RecursionGuard, Serialize, WireType,
+};
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+use zombie_core::deserialize::read_len;
+
+/// A JSON object.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Struct {
+    pub fields: BTreeMap<String, Value>,
+}
+
+/// A JSON array.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, Diff, Name)]
+#[zombie(syntax = "proto3", package = "google.protobuf")]
+pub struct ListValue {
+    #[id(1)]
+    pub values: Vec<Value>,
+}
+
+/// A JSON value. On the wire this is the `kind` oneof of `google.protobuf.Value`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Struct(Struct),
+    List(ListValue),
+}
+
+fn write_message(w: &mut impl Write, id: u64, message: &impl Serialize) -> io::Result<()> {
+    write_tag(w, WireType::Len, id)?;
+    let mut v = Vec::new();
+    message.serialize(&mut v)?;
+    write_uvarint(w, v.len() as u64)?;
+    w.write_all(&v[..])
+}
+
+fn read_message(
+    r: &mut impl Read,
+    wiretype: WireType,
+    message: &mut impl Deserialize,
+) -> Result<(), DeserializeError> {
+    if wiretype != WireType::Len {
+        return Err(DeserializeError::TypeError(format!(
+            "invalid wiretype for message: {:?}",
+            wiretype
+        )));
+    }
+    // Struct and Value contain each other, so nesting is only bounded by the input.
+    let _guard = RecursionGuard::enter()?;
+    let v = read_len(r)?;
+    message
+        .deserialize(&mut &v[..])
+        .map_err(|error| error.shift(uvarint_len(v.len() as u64)))
+}
+
+impl Serialize for Struct {
+    fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        write_message(w, id, self)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        for (key, value) in self.fields.iter() {
+            // Map entries always carry both their key and their value.
+            let mut entry = Vec::new();
+            key.serialize_field(1, ProtoType::String, &mut entry)?;
+            value.serialize_field(2, ProtoType::Message, &mut entry)?;
+            write_tag(w, WireType::Len, 1)?;
+            write_uvarint(w, entry.len() as u64)?;
+            w.write_all(&entry[..])?;
+        }
+        Ok(())
+    }
+}
+
+impl DeserializeField for Struct {
+    fn deserialize_field(
+        &mut self,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        read_message(r, wiretype, self)
+    }
+}
+
+impl Deserialize for Struct {
+    fn deserialize(&mut self, r: &mut impl Read) -> Result<(), DeserializeError> {
+        while let Some((id, wiretype)) = read_tag(r)? {
+            match id {
+                1 => {
+                    if wiretype != WireType::Len {
+                        return Err(DeserializeError::TypeError(format!(
+                            "invalid wiretype for map entry: {:?}",
+                            wiretype
+                        )));
+                    }
+                    let entry = read_len(r)?;
+                    let entry = &mut &entry[..];
+                    let mut key = String::new();
+                    let mut value = Value::Null;
+                    while let Some((id, wiretype)) = read_tag(entry)? {
+                        match id {
+                            1 => key.deserialize_field(ProtoType::String, wiretype, entry)?,
+                            2 => value.deserialize_field(ProtoType::Message, wiretype, entry)?,
+                            _ => skip_field(wiretype, entry)?,
+                        }
+                    }
+                    self.fields.insert(key, value);
+                }
+                _ => skip_field(wiretype, r)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProtoDefault for Struct {
+    fn proto_default() -> Self {
+        Struct::default()
+    }
+}
+
+impl MergeField for Struct {
+    fn merge_field(&mut self, other: &Self) {
+        for (key, value) in other.fields.iter() {
+            self.fields.insert(key.clone(), value.clone());
+        }
+    }
+
+    fn clear_field(&mut self) {
+        self.fields.clear();
+    }
+}
+
+impl Name for Struct {
+    const NAME: &'static str = "Struct";
+    const PACKAGE: &'static str = "google.protobuf";
+}
+
+impl Diff for Struct {
+    fn describe(&self) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{:?}: {}", key, value.describe()))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        let path = field_path(path, "fields");
+        for (key, value) in self.fields.iter() {
+            let path = format!("{}[{:?}]", path, key);
+            match other.fields.get(key) {
+                Some(other) => value.diff_field(other, &path, diff),
+                None => diff.removed(&path, value.describe()),
+            }
+        }
+        for (key, value) in other.fields.iter() {
+            if !self.fields.contains_key(key) {
+                diff.added(&format!("{}[{:?}]", path, key), value.describe());
+            }
+        }
+    }
+}
+
+impl Serialize for Value {
+    fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        write_message(w, id, self)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        // Oneof members are written even when they hold their default value.
+        match self {
+            Value::Null => {
+                write_tag(w, WireType::VarInt, 1)?;
+                write_uvarint(w, 0)
+            }
+            Value::Number(n) => n.serialize_field(2, ProtoType::Double, w),
+            Value::String(s) => s.serialize_field(3, ProtoType::String, w),
+            Value::Bool(b) => b.serialize_field(4, ProtoType::Bool, w),
+            Value::Struct(s) => s.serialize_field(5, ProtoType::Message, w),
+            Value::List(l) => l.serialize_field(6, ProtoType::Message, w),
+        }
+    }
+}
+
+impl DeserializeField for Value {
+    fn deserialize_field(
+        &mut self,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        read_message(r, wiretype, self)
+    }
+}
+
+impl Deserialize for Value {
+    fn deserialize(&mut self, r: &mut impl Read) -> Result<(), DeserializeError> {
+        while let Some((id, wiretype)) = read_tag(r)? {
+            match id {
+                1 => {
+                    let mut null = 0u64;
+                    null.deserialize_field(ProtoType::Enum, wiretype, r)?;
+                    *self = Value::Null;
+                }
+                2 => {
+                    let mut n = 0.0;
+                    n.deserialize_field(ProtoType::Double, wiretype, r)?;
+                    *self = Value::Number(n);
+                }
+                3 => {
+                    let mut s = String::new();
+                    s.deserialize_field(ProtoType::String, wiretype, r)?;
+                    *self = Value::String(s);
+                }
+                4 => {
+                    let mut b = false;
+                    b.deserialize_field(ProtoType::Bool, wiretype, r)?;
+                    *self = Value::Bool(b);
+                }
+                5 => {
+                    if !matches!(self, Value::Struct(_)) {
+                        *self = Value::Struct(Struct::default());
+                    }
+                    if let Value::Struct(s) = self {
+                        s.deserialize_field(ProtoType::Message, wiretype, r)?;
+                    }
+                }
+                6 => {
+                    if !matches!(self, Value::List(_)) {
+                        *self = Value::List(ListValue::default());
+                    }
+                    if let Value::List(l) = self {
+                        l.deserialize_field(ProtoType::Message, wiretype, r)?;
+                    }
+                }
+                _ => skip_field(wiretype, r)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ProtoDefault for Value {
+    fn proto_default() -> Self {
+        Value::Null
+    }
+}
+
+impl MergeField for Value {
+    fn merge_field(&mut self, other: &Self) {
+        *self = other.clone();
+    }
+
+    fn clear_field(&mut self) {
+        *self = Value::Null;
+    }
+}
+
+impl Name for Value {
+    const NAME: &'static str = "Value";
+    const PACKAGE: &'static str = "google.protobuf";
+}
+
+impl Diff for Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Null => "null".to_owned(),
+            Value::Number(n) => n.describe(),
+            Value::String(s) => s.describe(),
+            Value::Bool(b) => b.describe(),
+            Value::Struct(s) => s.describe(),
+            Value::List(l) => l.values.describe(),
+        }
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        match (self, other) {
+            (Value::Struct(a), Value::Struct(b)) => a.diff_field(b, path, diff),
+            (Value::List(a), Value::List(b)) => a.values.diff_field(&b.values, path, diff),
+            _ => {
+                let (from, to) = (self.describe(), other.describe());
+                if from != to {
+                    diff.changed(path, from, to);
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_struct(&self) -> Option<&Struct> {
+        match self {
+            Value::Struct(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(l) => Some(&l.values[..]),
+            _ => None,
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<Struct> for Value {
+    fn from(s: Struct) -> Self {
+        Value::Struct(s)
+    }
+}
+
+impl From<ListValue> for Value {
+    fn from(l: ListValue) -> Self {
+        Value::List(l)
+    }
+}
+
+impl From<BTreeMap<String, Value>> for Value {
+    fn from(fields: BTreeMap<String, Value>) -> Self {
+        Value::Struct(Struct { fields })
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        Value::List(ListValue {
+            values: values.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<Value> for Struct {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Struct(s) => Ok(s),
+            _ => Err(value),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = Value;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::List(l) => Ok(l.values),
+            _ => Err(value),
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Struct {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Struct {
+            fields: iter
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+impl<V: Into<Value>> FromIterator<V> for ListValue {
+    fn from_iter<I: IntoIterator<Item = V>>(iter: I) -> Self {
+        ListValue {
+            values: iter.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    fn example() -> Struct {
+        let inner: Struct = [("g", false)].into_iter().collect();
+        Struct {
+            fields: BTreeMap::from([
+                ("a".to_owned(), Value::from(1.0)),
+                ("b".to_owned(), Value::from("x")),
+                ("c".to_owned(), Value::Null),
+                ("d".to_owned(), Value::from(true)),
+                (
+                    "e".to_owned(),
+                    Value::from(vec![Value::from(1.0), Value::from("y")]),
+                ),
+                ("f".to_owned(), Value::from(inner)),
+            ]),
+        }
+    }
+
+    // Hand-encoded from google/protobuf/struct.proto rather than taken from this
+    // crate's output: each map entry is a `fields` (1) submessage holding the key (1)
+    // and the `Value` (2), whose kinds are null_value (1), number_value (2),
+    // string_value (3), bool_value (4), struct_value (5) and list_value (6). Entries
+    // are in key order, as `Struct` keeps them.
+    const EXAMPLE_BYTES: &[u8] = &[
+        0x0a, 0x0e, 0x0a, 0x01, 0x61, 0x12, 0x09, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0,
+        0x3f, // "a": 1.0
+        0x0a, 0x08, 0x0a, 0x01, 0x62, 0x12, 0x03, 0x1a, 0x01, 0x78, // "b": "x"
+        0x0a, 0x07, 0x0a, 0x01, 0x63, 0x12, 0x02, 0x08, 0x00, // "c": null
+        0x0a, 0x07, 0x0a, 0x01, 0x64, 0x12, 0x02, 0x20, 0x01, // "d": true
+        0x0a, 0x17, 0x0a, 0x01, 0x65, 0x12, 0x12, 0x32, 0x10, 0x0a, 0x09, 0x11, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0xf0, 0x3f, 0x0a, 0x03, 0x1a, 0x01, 0x79, // "e": [1.0, "y"]
+        0x0a, 0x10, 0x0a, 0x01, 0x66, 0x12, 0x0b, 0x2a, 0x09, 0x0a, 0x07, 0x0a, 0x01, 0x67, 0x12,
+        0x02, 0x20, 0x00, // "f": {"g": false}
+    ];
+
+    #[test]
+    fn struct_serialize() {
+        let mut v = Vec::new();
+        example().serialize(&mut v).unwrap();
+        assert_eq!(EXAMPLE_BYTES, &v[..]);
+    }
+
+    #[test]
+    fn struct_deserialize() {
+        assert_eq!(example(), Struct::decode_from_slice(EXAMPLE_BYTES).unwrap());
+    }
+
+    #[test]
+    fn value_null_serialize() {
+        let mut v = Vec::new();
+        Value::Null.serialize(&mut v).unwrap();
+        assert_eq!(vec![0x08, 0x00], v);
+    }
+
+    #[test]
+    fn list_value_round_trip() {
+        let list: ListValue = vec![Value::from(1.5), Value::Null].into_iter().collect();
+        let mut v = Vec::new();
+        list.serialize(&mut v).unwrap();
+        assert_eq!(
+            vec![
+                0x0a, 0x09, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x3f, // 1.5
+                0x0a, 0x02, 0x08, 0x00, // null
+            ],
+            v
+        );
+        assert_eq!(list, ListValue::decode_from_slice(&v[..]).unwrap());
+    }
+
+    #[test]
+    fn value_last_kind_wins() {
+        let v = [
+            0x20, 0x01, // bool_value
+            0x1a, 0x01, 0x7a, // string_value
+        ];
+        assert_eq!(Value::from("z"), Value::decode_from_slice(&v[..]).unwrap());
+    }
+
+    #[test]
+    fn value_rejects_non_message_wiretypes() {
+        // struct_value as a varint
+        let v = [0x28, 0x01];
+        assert!(matches!(
+            Value::decode_from_slice(&v[..]),
+            Err(DeserializeError::TypeError(_))
+        ));
+        // A Struct entry whose value is a fixed32
+        let v = [0x0a, 0x05, 0x15, 0x00, 0x00, 0x00, 0x00];
+        assert!(matches!(
+            Struct::decode_from_slice(&v[..]),
+            Err(DeserializeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn value_recursion_limit() {
+        // {"": {"": {"": ...}}}, far deeper than the limit.
+        // Headers from the innermost out, so that each length is known when written.
+        let mut headers = Vec::new();
+        let mut len = 0;
+        for _ in 0..50_000 {
+            // struct_value, the entry's value and the Struct's entry
+            for tag in [0x2a, 0x12, 0x0a] {
+                let mut header = vec![tag];
+                write_uvarint(&mut header, len as u64).unwrap();
+                len += header.len();
+                headers.push(header);
+            }
+        }
+        let v: Vec<u8> = headers.into_iter().rev().flatten().collect();
+        assert!(matches!(
+            Struct::decode_from_slice(&v[..]),
+            Err(DeserializeError::RecursionLimitExceeded)
+        ));
+    }
+
+    #[test]
+    fn value_conversions() {
+        assert_eq!(Value::Null, Value::from(None::<f64>));
+        assert_eq!(Some(2.0), Value::from(Some(2.0)).as_f64());
+        assert_eq!(Ok("s".to_owned()), String::try_from(Value::from("s")));
+        assert_eq!(Err(Value::Null), bool::try_from(Value::Null));
+        let s = example();
+        let value = Value::from(s.clone());
+        assert_eq!(Some(true), value.as_struct().unwrap().fields["d"].as_bool());
+        assert_eq!(
+            2,
+            value.as_struct().unwrap().fields["e"]
+                .as_list()
+                .unwrap()
+                .len()
+        );
+        assert_eq!(Ok(s), Struct::try_from(value));
+    }
+
+    #[test]
+    fn struct_merge_replaces_entries() {
+        let mut s: Struct = [("a", 1.0), ("b", 2.0)].into_iter().collect();
+        s.merge(&[("b", 3.0)].into_iter().collect());
+        assert_eq!(Some(1.0), s.fields["a"].as_f64());
+        assert_eq!(Some(3.0), s.fields["b"].as_f64());
+    }
+}
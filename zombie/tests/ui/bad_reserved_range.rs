@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+#[reserved(11 to 9)]
+struct Message {
+    #[id(1)]
+    a: i32,
+}
+
+fn main() {}
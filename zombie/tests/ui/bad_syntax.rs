@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+#[zombie(syntax = "proto4")]
+struct Message {
+    #[id(1)]
+    a: i32,
+}
+
+fn main() {}
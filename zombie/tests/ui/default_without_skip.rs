@@ -0,0 +1,14 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+struct Message {
+    #[id(1)]
+    #[zombie(default = "make_session")]
+    session: u32,
+}
+
+fn make_session() -> u32 {
+    7
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    a: i32,
+
+    #[id(1)]
+    b: i32,
+}
+
+fn main() {}
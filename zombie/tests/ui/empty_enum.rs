@@ -0,0 +1,6 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+enum Empty {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(19500)]
+    a: i32,
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    #[pbtype(sint32)]
+    name: String,
+
+    #[id(2)]
+    #[pbtype(fixed64)]
+    count: Option<i32>,
+}
+
+fn main() {}
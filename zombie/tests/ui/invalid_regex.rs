@@ -0,0 +1,10 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+struct Message {
+    #[id(1)]
+    #[validate(regex = "[a-z")]
+    name: String,
+}
+
+fn main() {}
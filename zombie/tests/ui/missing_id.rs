@@ -0,0 +1,13 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    a: i32,
+
+    b: i32,
+
+    c: String,
+}
+
+fn main() {}
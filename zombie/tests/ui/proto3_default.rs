@@ -0,0 +1,11 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+#[zombie(syntax = "proto3")]
+struct Message {
+    #[id(1)]
+    #[default(7)]
+    count: i32,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+#[zombie(syntax = "proto3")]
+struct Message {
+    #[id(1)]
+    #[required]
+    count: i32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+struct Message {
+    #[id(1)]
+    #[validate(required)]
+    name: String,
+}
+
+fn main() {}
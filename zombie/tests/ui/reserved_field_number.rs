@@ -0,0 +1,13 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+#[reserved(2, 15, 9 to 11, "old_name")]
+struct Message {
+    #[id(1)]
+    a: i32,
+
+    #[id(10)]
+    b: i32,
+}
+
+fn main() {}
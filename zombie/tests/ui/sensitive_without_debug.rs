@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Message {
+    #[id(1)]
+    #[sensitive]
+    password: String,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message(i32, String);
+
+fn main() {}
@@ -0,0 +1,9 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+union Message {
+    a: i32,
+    b: u32,
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use zombie::Deserialize;
+
+#[derive(Deserialize)]
+struct Message;
+
+fn main() {}
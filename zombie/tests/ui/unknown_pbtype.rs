@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    #[pbtype(sint23)]
+    a: i32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+#[zombie(packed = true)]
+struct Message {
+    #[id(1)]
+    a: i32,
+}
+
+fn main() {}
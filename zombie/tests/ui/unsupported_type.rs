@@ -0,0 +1,9 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    pair: (i32, i32),
+}
+
+fn main() {}
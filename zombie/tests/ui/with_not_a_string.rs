@@ -0,0 +1,10 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(1)]
+    #[zombie(with = 5)]
+    addr: std::net::Ipv4Addr,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use zombie::Serialize;
+
+#[derive(Serialize)]
+struct Message {
+    #[id(0)]
+    a: i32,
+}
+
+fn main() {}
@@ -0,0 +1,128 @@
+#!/usr/bin/env python3
+"""Writes requests.bin and responses.bin for test_golden_session.
+
+The bytes are encoded by hand from the protobuf wire format, independently of
+zombie. They are NOT recorded from the reference implementation or the upstream
+conformance runner, so they only pin down zombie's behavior as we understand the
+spec. Run from this directory: python3 generate.py
+"""
+
+import struct
+
+
+def varint(n):
+    out = []
+    while True:
+        b = n & 0x7F
+        n >>= 7
+        if n:
+            out.append(b | 0x80)
+        else:
+            out.append(b)
+            return out
+
+
+def tag(field, wiretype):
+    return varint(field << 3 | wiretype)
+
+
+def ld(field, payload):
+    return tag(field, 2) + varint(len(payload)) + payload
+
+
+def s(field, text):
+    return ld(field, list(text.encode()))
+
+
+def vi(field, n):
+    return tag(field, 0) + varint(n)
+
+
+def frame(payload):
+    return list(struct.pack("<I", len(payload))) + payload
+
+
+# ConformanceRequest: protobuf_payload = 1, json_payload = 2,
+# requested_output_format = 3, message_type = 4, test_category = 5.
+def req(payload=None, json=None, fmt=1, mt="", cat=1):
+    out = []
+    if payload is not None:
+        out += ld(1, payload)
+    if json is not None:
+        out += s(2, json)
+    if fmt:
+        out += vi(3, fmt)
+    out += s(4, mt)
+    if cat:
+        out += vi(5, cat)
+    return out
+
+
+P3 = "protobuf_test_messages.proto3.TestAllTypesProto3"
+P2 = "protobuf_test_messages.proto2.TestAllTypesProto2"
+F32 = list(struct.pack("<f", 1.5))
+
+# Eleven bytes, one more than any varint may take.
+OVERLONG = [0x80] * 10 + [0x01]
+
+# (request, response) pairs. ConformanceResponse: parse_error = 1, runtime_error = 2,
+# protobuf_payload = 3, skipped = 5.
+session = [
+    # The runner asks for the failure set first.
+    (req(fmt=1, mt="conformance.FailureSet", cat=0), ld(3, [])),
+    # proto3 round trip: fields out of order, defaults dropped on output.
+    (
+        req(
+            payload=vi(31, 1) + vi(21, 1) + ld(18, vi(1, 1)) + s(14, "hi") + vi(1, 150)
+            + vi(13, 0) + tag(11, 5) + F32 + vi(31, 2) + vi(5, 3),
+            mt=P3,
+        ),
+        ld(
+            3,
+            vi(1, 150) + vi(5, 3) + tag(11, 5) + F32 + s(14, "hi") + ld(18, vi(1, 1))
+            + vi(21, 1) + vi(31, 1) + vi(31, 2),
+        ),
+    ),
+    # proto2 keeps explicitly set zero values; negative enum.
+    (
+        req(payload=vi(1, 0) + vi(13, 0) + vi(21, (1 << 64) - 1) + ld(15, []), mt=P2),
+        ld(3, vi(1, 0) + vi(13, 0) + ld(15, []) + vi(21, (1 << 64) - 1)),
+    ),
+    (req(json="{}", mt=P3, cat=2), s(5, "JSON input")),
+    (req(payload=vi(1, 1), fmt=4, mt=P3, cat=5), s(5, "TextFormat output")),
+    (
+        req(payload=[], mt="protobuf_test_messages.editions.TestAllTypesEdition2023"),
+        s(5, "message type protobuf_test_messages.editions.TestAllTypesEdition2023"),
+    ),
+    # Malformed payloads are parse errors, not crashes.
+    (
+        req(payload=vi(1, 150) + tag(1, 0) + OVERLONG, mt=P3),
+        s(
+            1,
+            "failed to decode TestAllTypesProto3.optional_int32 (field 1, wire type VarInt) "
+            "at byte 4: io error: varint longer than 10 bytes",
+        ),
+    ),
+    (
+        req(payload=tag(14, 2) + varint((1 << 64) - 1) + list(b"hi"), mt=P3),
+        s(
+            1,
+            "failed to decode TestAllTypesProto3.optional_string (field 14, wire type Len) "
+            "at byte 1: io error: expected 18446744073709551615 bytes, got 2",
+        ),
+    ),
+    # A request that can't be read at all still gets an answer.
+    (
+        tag(3, 0) + OVERLONG,
+        s(
+            2,
+            "failed to decode ConformanceRequest.requested_output_format "
+            "(field 3, wire type VarInt) at byte 1: io error: varint longer than 10 bytes",
+        ),
+    ),
+]
+
+with open("requests.bin", "wb") as f:
+    f.write(bytes(sum((frame(request) for request, _ in session), [])))
+with open("responses.bin", "wb") as f:
+    f.write(bytes(sum((frame(response) for _, response in session), [])))
@@ -0,0 +1,131 @@
+//! Messages from `conformance/conformance.proto`, the protocol spoken between the
+//! upstream conformance runner and a testee.
+//!
+//! zombie has no oneof support, so the `payload` and `result` oneofs are modelled as
+//! optional fields of which at most one is set.
+
+use zombie::{Deserialize, DeserializeField, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WireFormat {
+    Unspecified = 0,
+    Protobuf = 1,
+    Json = 2,
+    Jspb = 3,
+    TextFormat = 4,
+}
+
+impl TryFrom<u64> for WireFormat {
+    type Error = std::io::Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(WireFormat::Unspecified),
+            1 => Ok(WireFormat::Protobuf),
+            2 => Ok(WireFormat::Json),
+            3 => Ok(WireFormat::Jspb),
+            4 => Ok(WireFormat::TextFormat),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid WireFormat value: {}", value),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestCategory {
+    Unspecified = 0,
+    Binary = 1,
+    Json = 2,
+    JsonIgnoreUnknownParsing = 3,
+    Jspb = 4,
+    TextFormat = 5,
+}
+
+impl TryFrom<u64> for TestCategory {
+    type Error = std::io::Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TestCategory::Unspecified),
+            1 => Ok(TestCategory::Binary),
+            2 => Ok(TestCategory::Json),
+            3 => Ok(TestCategory::JsonIgnoreUnknownParsing),
+            4 => Ok(TestCategory::Jspb),
+            5 => Ok(TestCategory::TextFormat),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid TestCategory value: {}", value),
+            )),
+        }
+    }
+}
+
+/// Sent by the runner as the payload of its first request; the testee answers with
+/// the failures it expects, or an empty set to defer to `--failure_list`.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[zombie(syntax = "proto3", package = "conformance")]
+pub struct FailureSet {
+    #[id(1)]
+    pub failure: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[zombie(syntax = "proto3", package = "conformance")]
+pub struct ConformanceRequest {
+    #[id(1)]
+    pub protobuf_payload: Option<Vec<u8>>,
+
+    #[id(2)]
+    pub json_payload: Option<String>,
+
+    #[id(3)]
+    pub requested_output_format: WireFormat,
+
+    #[id(4)]
+    pub message_type: String,
+
+    #[id(5)]
+    pub test_category: TestCategory,
+
+    #[id(7)]
+    pub jspb_payload: Option<String>,
+
+    #[id(8)]
+    pub text_payload: Option<String>,
+
+    #[id(9)]
+    pub print_unknown_fields: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[zombie(syntax = "proto3", package = "conformance")]
+pub struct ConformanceResponse {
+    #[id(1)]
+    pub parse_error: Option<String>,
+
+    #[id(2)]
+    pub runtime_error: Option<String>,
+
+    #[id(3)]
+    pub protobuf_payload: Option<Vec<u8>>,
+
+    #[id(4)]
+    pub json_payload: Option<String>,
+
+    #[id(5)]
+    pub skipped: Option<String>,
+
+    #[id(6)]
+    pub serialize_error: Option<String>,
+
+    #[id(7)]
+    pub jspb_payload: Option<String>,
+
+    #[id(8)]
+    pub text_payload: Option<String>,
+
+    #[id(9)]
+    pub timeout_error: Option<String>,
+}
@@ -0,0 +1,162 @@
+//! A testee for the upstream protobuf conformance suite.
+//!
+//! The runner starts the testee as a subprocess and exchanges `ConformanceRequest` and
+//! `ConformanceResponse` messages with it, each prefixed by its length as a
+//! little-endian `u32`, over stdin and stdout.
+
+pub mod conformance;
+pub mod test_messages;
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use conformance::{ConformanceRequest, ConformanceResponse, FailureSet, WireFormat};
+use test_messages::{TestAllTypesProto2, TestAllTypesProto3};
+use zombie::{Message, Name, ProtoDefault};
+
+fn response(f: impl FnOnce(&mut ConformanceResponse)) -> ConformanceResponse {
+    let mut response = ConformanceResponse::proto_default();
+    f(&mut response);
+    response
+}
+
+fn round_trip<T: Message>(request: &ConformanceRequest) -> ConformanceResponse {
+    let message = match (&request.protobuf_payload, &request.json_payload) {
+        (Some(payload), _) => match T::decode(payload) {
+            Ok(message) => message,
+            Err(error) => return response(|r| r.parse_error = Some(error.to_string())),
+        },
+        (None, Some(_)) => return response(|r| r.skipped = Some("JSON input".to_owned())),
+        (None, None) => {
+            return response(|r| r.skipped = Some("JSPB and text input".to_owned()));
+        }
+    };
+
+    match request.requested_output_format {
+        WireFormat::Protobuf => match message.encode_to_vec() {
+            Ok(v) => response(|r| r.protobuf_payload = Some(v)),
+            Err(error) => response(|r| r.serialize_error = Some(error.to_string())),
+        },
+        format => response(|r| r.skipped = Some(format!("{:?} output", format))),
+    }
+}
+
+pub fn run_test(request: &ConformanceRequest) -> ConformanceResponse {
+    match request.message_type.as_str() {
+        "conformance.FailureSet" => {
+            let v = FailureSet::proto_default().encode_to_vec().unwrap();
+            response(|r| r.protobuf_payload = Some(v))
+        }
+        name if name == TestAllTypesProto3::full_name() => {
+            round_trip::<TestAllTypesProto3>(request)
+        }
+        name if name == TestAllTypesProto2::full_name() => {
+            round_trip::<TestAllTypesProto2>(request)
+        }
+        name => response(|r| r.skipped = Some(format!("message type {}", name))),
+    }
+}
+
+/// Answers one request from `r` on `w`, returning `false` once the runner has closed
+/// its end of the pipe.
+pub fn serve_one(r: &mut impl Read, w: &mut impl Write) -> io::Result<bool> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+        Err(error) => return Err(error),
+    }
+    // Read through `take` rather than allocating the whole frame length up front.
+    let len = u32::from_le_bytes(len) as u64;
+    let mut buf = Vec::new();
+    r.take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "truncated request",
+        ));
+    }
+
+    let response = match ConformanceRequest::decode(&buf) {
+        Ok(request) => run_test(&request),
+        Err(error) => response(|r| r.runtime_error = Some(error.to_string())),
+    };
+
+    let v = response.encode_to_vec()?;
+    w.write_all(&(v.len() as u32).to_le_bytes())?;
+    w.write_all(&v)?;
+    w.flush()?;
+    Ok(true)
+}
+
+pub fn serve(r: &mut impl Read, w: &mut impl Write) -> io::Result<()> {
+    while serve_one(r, w)? {}
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(mut bytes: &[u8]) -> Vec<&[u8]> {
+        let mut out = Vec::new();
+        while !bytes.is_empty() {
+            let (len, rest) = bytes.split_at(4);
+            let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+            let (frame, rest) = rest.split_at(len);
+            out.push(frame);
+            bytes = rest;
+        }
+        out
+    }
+
+    /// A golden self-test, not a recording: `fixtures/generate.py` encodes the session
+    /// by hand from the wire format, and regenerates it. The upstream runner is what
+    /// checks zombie against the reference implementation.
+    #[test]
+    fn test_golden_session() {
+        let requests = include_bytes!("../fixtures/requests.bin");
+        let expected = include_bytes!("../fixtures/responses.bin");
+
+        let mut out = Vec::new();
+        serve(&mut &requests[..], &mut out).unwrap();
+
+        let actual = frames(&out);
+        let expected = frames(expected);
+        assert_eq!(expected.len(), actual.len());
+        for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+            assert_eq!(expected, actual, "response {}", i);
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let request = ConformanceRequest {
+            protobuf_payload: Some(vec![0x08]),
+            requested_output_format: WireFormat::Protobuf,
+            message_type: "protobuf_test_messages.proto3.TestAllTypesProto3".to_owned(),
+            ..ConformanceRequest::proto_default()
+        };
+        let response = run_test(&request);
+        assert!(response.parse_error.is_some());
+        assert_eq!(None, response.protobuf_payload);
+    }
+
+    #[test]
+    fn test_json_skipped() {
+        let request = ConformanceRequest {
+            json_payload: Some("{}".to_owned()),
+            requested_output_format: WireFormat::Protobuf,
+            message_type: "protobuf_test_messages.proto3.TestAllTypesProto3".to_owned(),
+            ..ConformanceRequest::proto_default()
+        };
+        let response = run_test(&request);
+        assert_eq!(Some("JSON input".to_owned()), response.skipped);
+    }
+
+    #[test]
+    fn test_serve_stops_at_eof() {
+        let mut out = Vec::new();
+        assert!(!serve_one(&mut &[][..], &mut out).unwrap());
+        assert!(out.is_empty());
+    }
+}
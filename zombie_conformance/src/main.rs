@@ -0,0 +1,5 @@
+fn main() -> std::io::Result<()> {
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    zombie_conformance::serve(&mut stdin.lock(), &mut stdout.lock())
+}
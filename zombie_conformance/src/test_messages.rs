@@ -0,0 +1,338 @@
+//! The subset of `TestAllTypesProto3` and `TestAllTypesProto2` that zombie can model:
+//! singular and repeated scalars, submessages and enums. Maps, oneofs, groups and
+//! well-known-type fields are absent, so the runner's tests that use them are listed
+//! in `failure_list_zombie.txt`.
+
+use zombie::{Deserialize, DeserializeField, Name, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NestedEnum {
+    Foo = 0,
+    Bar = 1,
+    Baz = 2,
+    Neg = -1,
+}
+
+impl TryFrom<u64> for NestedEnum {
+    type Error = std::io::Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value as i64 {
+            0 => Ok(NestedEnum::Foo),
+            1 => Ok(NestedEnum::Bar),
+            2 => Ok(NestedEnum::Baz),
+            -1 => Ok(NestedEnum::Neg),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid NestedEnum value: {}", value),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForeignEnum {
+    ForeignFoo = 0,
+    ForeignBar = 1,
+    ForeignBaz = 2,
+}
+
+impl TryFrom<u64> for ForeignEnum {
+    type Error = std::io::Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ForeignEnum::ForeignFoo),
+            1 => Ok(ForeignEnum::ForeignBar),
+            2 => Ok(ForeignEnum::ForeignBaz),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("invalid ForeignEnum value: {}", value),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct NestedMessage {
+    #[id(1)]
+    pub a: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ForeignMessage {
+    #[id(1)]
+    pub c: Option<i32>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Name)]
+#[zombie(syntax = "proto3", package = "protobuf_test_messages.proto3")]
+pub struct TestAllTypesProto3 {
+    #[id(1)]
+    pub optional_int32: i32,
+
+    #[id(2)]
+    pub optional_int64: i64,
+
+    #[id(3)]
+    pub optional_uint32: u32,
+
+    #[id(4)]
+    pub optional_uint64: u64,
+
+    #[id(5)]
+    #[pbtype(sint32)]
+    pub optional_sint32: i32,
+
+    #[id(6)]
+    #[pbtype(sint64)]
+    pub optional_sint64: i64,
+
+    #[id(7)]
+    #[pbtype(fixed32)]
+    pub optional_fixed32: u32,
+
+    #[id(8)]
+    #[pbtype(fixed64)]
+    pub optional_fixed64: u64,
+
+    #[id(9)]
+    #[pbtype(sfixed32)]
+    pub optional_sfixed32: i32,
+
+    #[id(10)]
+    #[pbtype(sfixed64)]
+    pub optional_sfixed64: i64,
+
+    #[id(11)]
+    #[pbtype(float)]
+    pub optional_float: f32,
+
+    #[id(12)]
+    #[pbtype(double)]
+    pub optional_double: f64,
+
+    #[id(13)]
+    pub optional_bool: bool,
+
+    #[id(14)]
+    pub optional_string: String,
+
+    #[id(15)]
+    #[pbtype(bytes)]
+    pub optional_bytes: Vec<u8>,
+
+    #[id(18)]
+    pub optional_nested_message: Option<NestedMessage>,
+
+    #[id(19)]
+    pub optional_foreign_message: Option<ForeignMessage>,
+
+    #[id(21)]
+    pub optional_nested_enum: NestedEnum,
+
+    #[id(22)]
+    pub optional_foreign_enum: ForeignEnum,
+
+    #[id(31)]
+    pub repeated_int32: Vec<i32>,
+
+    #[id(32)]
+    pub repeated_int64: Vec<i64>,
+
+    #[id(33)]
+    pub repeated_uint32: Vec<u32>,
+
+    #[id(34)]
+    pub repeated_uint64: Vec<u64>,
+
+    #[id(35)]
+    #[pbtype(sint32)]
+    pub repeated_sint32: Vec<i32>,
+
+    #[id(36)]
+    #[pbtype(sint64)]
+    pub repeated_sint64: Vec<i64>,
+
+    #[id(37)]
+    #[pbtype(fixed32)]
+    pub repeated_fixed32: Vec<u32>,
+
+    #[id(38)]
+    #[pbtype(fixed64)]
+    pub repeated_fixed64: Vec<u64>,
+
+    #[id(39)]
+    #[pbtype(sfixed32)]
+    pub repeated_sfixed32: Vec<i32>,
+
+    #[id(40)]
+    #[pbtype(sfixed64)]
+    pub repeated_sfixed64: Vec<i64>,
+
+    #[id(41)]
+    #[pbtype(float)]
+    pub repeated_float: Vec<f32>,
+
+    #[id(42)]
+    #[pbtype(double)]
+    pub repeated_double: Vec<f64>,
+
+    #[id(43)]
+    pub repeated_bool: Vec<bool>,
+
+    #[id(44)]
+    pub repeated_string: Vec<String>,
+
+    #[id(45)]
+    #[pbtype(bytes)]
+    pub repeated_bytes: Vec<Vec<u8>>,
+
+    #[id(48)]
+    pub repeated_nested_message: Vec<NestedMessage>,
+
+    #[id(49)]
+    pub repeated_foreign_message: Vec<ForeignMessage>,
+
+    #[id(51)]
+    pub repeated_nested_enum: Vec<NestedEnum>,
+
+    #[id(52)]
+    pub repeated_foreign_enum: Vec<ForeignEnum>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Name)]
+#[zombie(syntax = "proto2", package = "protobuf_test_messages.proto2")]
+pub struct TestAllTypesProto2 {
+    #[id(1)]
+    pub optional_int32: Option<i32>,
+
+    #[id(2)]
+    pub optional_int64: Option<i64>,
+
+    #[id(3)]
+    pub optional_uint32: Option<u32>,
+
+    #[id(4)]
+    pub optional_uint64: Option<u64>,
+
+    #[id(5)]
+    #[pbtype(sint32)]
+    pub optional_sint32: Option<i32>,
+
+    #[id(6)]
+    #[pbtype(sint64)]
+    pub optional_sint64: Option<i64>,
+
+    #[id(7)]
+    #[pbtype(fixed32)]
+    pub optional_fixed32: Option<u32>,
+
+    #[id(8)]
+    #[pbtype(fixed64)]
+    pub optional_fixed64: Option<u64>,
+
+    #[id(9)]
+    #[pbtype(sfixed32)]
+    pub optional_sfixed32: Option<i32>,
+
+    #[id(10)]
+    #[pbtype(sfixed64)]
+    pub optional_sfixed64: Option<i64>,
+
+    #[id(11)]
+    #[pbtype(float)]
+    pub optional_float: Option<f32>,
+
+    #[id(12)]
+    #[pbtype(double)]
+    pub optional_double: Option<f64>,
+
+    #[id(13)]
+    pub optional_bool: Option<bool>,
+
+    #[id(14)]
+    pub optional_string: Option<String>,
+
+    #[id(15)]
+    #[pbtype(bytes)]
+    pub optional_bytes: Option<Vec<u8>>,
+
+    #[id(18)]
+    pub optional_nested_message: Option<NestedMessage>,
+
+    #[id(19)]
+    pub optional_foreign_message: Option<ForeignMessage>,
+
+    #[id(21)]
+    pub optional_nested_enum: Option<NestedEnum>,
+
+    #[id(22)]
+    pub optional_foreign_enum: Option<ForeignEnum>,
+
+    #[id(31)]
+    pub repeated_int32: Vec<i32>,
+
+    #[id(32)]
+    pub repeated_int64: Vec<i64>,
+
+    #[id(33)]
+    pub repeated_uint32: Vec<u32>,
+
+    #[id(34)]
+    pub repeated_uint64: Vec<u64>,
+
+    #[id(35)]
+    #[pbtype(sint32)]
+    pub repeated_sint32: Vec<i32>,
+
+    #[id(36)]
+    #[pbtype(sint64)]
+    pub repeated_sint64: Vec<i64>,
+
+    #[id(37)]
+    #[pbtype(fixed32)]
+    pub repeated_fixed32: Vec<u32>,
+
+    #[id(38)]
+    #[pbtype(fixed64)]
+    pub repeated_fixed64: Vec<u64>,
+
+    #[id(39)]
+    #[pbtype(sfixed32)]
+    pub repeated_sfixed32: Vec<i32>,
+
+    #[id(40)]
+    #[pbtype(sfixed64)]
+    pub repeated_sfixed64: Vec<i64>,
+
+    #[id(41)]
+    #[pbtype(float)]
+    pub repeated_float: Vec<f32>,
+
+    #[id(42)]
+    #[pbtype(double)]
+    pub repeated_double: Vec<f64>,
+
+    #[id(43)]
+    pub repeated_bool: Vec<bool>,
+
+    #[id(44)]
+    pub repeated_string: Vec<String>,
+
+    #[id(45)]
+    #[pbtype(bytes)]
+    pub repeated_bytes: Vec<Vec<u8>>,
+
+    #[id(48)]
+    pub repeated_nested_message: Vec<NestedMessage>,
+
+    #[id(49)]
+    pub repeated_foreign_message: Vec<ForeignMessage>,
+
+    #[id(51)]
+    pub repeated_nested_enum: Vec<NestedEnum>,
+
+    #[id(52)]
+    pub repeated_foreign_enum: Vec<ForeignEnum>,
+}
@@ -0,0 +1,207 @@
+use crate::{
+    descriptor::{bounded_generics, FieldDesc, Shape},
+    proto_type::{infer_proto_type, ProtoType},
+};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::{format_ident, quote};
+use syn::{Generics, Type, Visibility};
+
+/// A message with a `#[zombie(builder)]` builder.
+pub trait Buildable: Sized {
+    type Builder: Builder<Message = Self>;
+
+    fn builder() -> Self::Builder;
+}
+
+pub trait Builder {
+    type Message;
+
+    fn build(self) -> Self::Message;
+}
+
+/// Links a message to its builder through a type parameter, so that a submessage
+/// setter can be declared for a field type that turns out not to be `Buildable`
+/// (such as an enum) and only fails to resolve if it is called.
+pub trait BuiltWith<B>: Sized {
+    fn start() -> B;
+    fn finish(builder: B) -> Self;
+}
+
+impl<M: Buildable> BuiltWith<M::Builder> for M {
+    fn start() -> M::Builder {
+        M::builder()
+    }
+
+    fn finish(builder: M::Builder) -> M {
+        builder.build()
+    }
+}
+
+impl FieldDesc {
+    /// Whether the field looks like a submessage, which gets a setter taking a closure
+    /// over the submessage's builder. Enums look the same; see `BuiltWith`.
+    fn is_submessage(&self, ty: &Type) -> bool {
+        self.with.is_none() && matches!(infer_proto_type(ty), Ok(ProtoType::Other))
+    }
+
+    fn builder_setters(&self) -> TokenStream {
+        let ident = &self.name;
+        let rust_ty = &self.rust_ty;
+        let with_ident = format_ident!("with_{}", ident);
+        let with_setter = quote! {
+            pub fn #with_ident(mut self, value: #rust_ty) -> Self {
+                self.message.#ident = value;
+                self
+            }
+        };
+
+        let (ty, wrap) = match self.shape() {
+            Shape::Plain => (rust_ty.clone(), quote! { value }),
+            Shape::Optional(inner) => (inner, quote! { Some(value) }),
+            Shape::Repeated(inner) => {
+                let push_ident = format_ident!("push_{}", ident);
+                let extend_ident = format_ident!("extend_{}", ident);
+                return quote! {
+                    #with_setter
+
+                    pub fn #ident(mut self, value: impl Into<#rust_ty>) -> Self {
+                        self.message.#ident = value.into();
+                        self
+                    }
+
+                    pub fn #push_ident(mut self, value: impl Into<#inner>) -> Self {
+                        self.message.#ident.push(value.into());
+                        self
+                    }
+
+                    pub fn #extend_ident(mut self, values: impl IntoIterator<Item = #inner>) -> Self {
+                        self.message.#ident.extend(values);
+                        self
+                    }
+                };
+            }
+        };
+
+        if self.is_submessage(&ty) {
+            quote! {
+                #with_setter
+
+                pub fn #ident<B>(mut self, f: impl FnOnce(B) -> B) -> Self
+                where
+                    #ty: zombie::BuiltWith<B>,
+                {
+                    let builder = f(<#ty as zombie::BuiltWith<B>>::start());
+                    let value = <#ty as zombie::BuiltWith<B>>::finish(builder);
+                    self.message.#ident = #wrap;
+                    self
+                }
+            }
+        } else {
+            quote! {
+                #with_setter
+
+                pub fn #ident(mut self, value: impl Into<#ty>) -> Self {
+                    let value = value.into();
+                    self.message.#ident = #wrap;
+                    self
+                }
+            }
+        }
+    }
+
+    fn presence_helpers(&self, vis: &Visibility) -> TokenStream {
+        let ident = &self.name;
+        let inner = match self.shape() {
+            Shape::Optional(inner) => inner,
+            _ => return quote! {},
+        };
+        let has_ident = format_ident!("has_{}", ident);
+        let set_ident = format_ident!("set_{}", ident);
+        let clear_ident = format_ident!("clear_{}", ident);
+        quote! {
+            #vis fn #has_ident(&self) -> bool {
+                self.#ident.is_some()
+            }
+
+            #vis fn #set_ident(&mut self, value: impl Into<#inner>) {
+                self.#ident = Some(value.into());
+            }
+
+            #vis fn #clear_ident(&mut self) {
+                self.#ident = None;
+            }
+        }
+    }
+}
+
+pub(crate) fn derive_builder(
+    name: &Ident,
+    vis: &Visibility,
+    generics: &Generics,
+    fields: &[FieldDesc],
+) -> TokenStream {
+    let builder = format_ident!("{}Builder", name);
+    let doc = format!(
+        "Builds a [`{}`] field by field, starting from its proto defaults.",
+        name
+    );
+    let struct_where_clause = &generics.where_clause;
+
+    let bounded = bounded_generics(generics, quote! { zombie::ProtoDefault });
+    let (impl_generics, ty_generics, where_clause) = bounded.split_for_impl();
+
+    let setters = fields
+        .iter()
+        .map(|field| field.builder_setters())
+        .collect::<Vec<TokenStream>>();
+    let helpers = fields
+        .iter()
+        .map(|field| field.presence_helpers(vis))
+        .collect::<Vec<TokenStream>>();
+
+    quote! {
+        #[doc = #doc]
+        #vis struct #builder #generics #struct_where_clause {
+            message: #name #ty_generics,
+        }
+
+        #[automatically_derived]
+        impl #impl_generics #builder #ty_generics #where_clause {
+            #(#setters)*
+
+            pub fn build(self) -> #name #ty_generics {
+                self.message
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics #name #ty_generics #where_clause {
+            #vis fn builder() -> #builder #ty_generics {
+                #builder {
+                    message: zombie::ProtoDefault::proto_default(),
+                }
+            }
+
+            #(#helpers)*
+        }
+
+        #[automatically_derived]
+        impl #impl_generics zombie::Buildable for #name #ty_generics #where_clause {
+            type Builder = #builder #ty_generics;
+
+            fn builder() -> Self::Builder {
+                #name::builder()
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics zombie::Builder for #builder #ty_generics #where_clause {
+            type Message = #name #ty_generics;
+
+            fn build(self) -> Self::Message {
+                self.message
+            }
+        }
+    }
+}
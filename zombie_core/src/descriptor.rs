@@ -1,54 +1,531 @@
-use crate::proto_type::{infer_proto_type, ProtoType};
+use crate::proto_type::{
+    infer_proto_type, is_option_type, option_inner_type, vec_inner_type, ProtoType,
+};
+use crate::validate::{extract_validation_rules, ValidationRule};
 
-use anyhow::{anyhow, Result};
-use proc_macro2::Ident;
-use syn::{DataStruct, LitInt};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use std::collections::HashMap;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_quote,
+    punctuated::Punctuated,
+    Attribute, DataStruct, Error, Expr, ExprLit, Field, Fields, Generics, Lit, LitInt, LitStr,
+    Meta, NestedMeta, Path, Result, Token, Type,
+};
+
+pub const MAX_FIELD_NUMBER: u64 = (1 << 29) - 1;
+
+/// Field numbers protobuf keeps for its own implementation.
+const IMPLEMENTATION_RESERVED: (u64, u64) = (19000, 19999);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    Proto2,
+    Proto3,
+}
+
+pub struct MessageOptions {
+    pub syntax: Syntax,
+    pub package: String,
+    pub reserved: Vec<Reserved>,
+    pub builder: bool,
+    pub debug: bool,
+    pub validate_on_decode: bool,
+}
+
+/// One entry of a `#[reserved(2, 9 to 11, "old_name")]` attribute.
+pub enum Reserved {
+    Range { start: u64, end: u64 },
+    Name(String),
+}
+
+impl Parse for Reserved {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            let name: LitStr = input.parse()?;
+            return Ok(Reserved::Name(name.value()));
+        }
+
+        let lit: LitInt = input.parse()?;
+        let start: u64 = lit.base10_parse()?;
+        if start == 0 || start > MAX_FIELD_NUMBER {
+            return Err(Error::new_spanned(
+                lit,
+                "reserved field number is out of range",
+            ));
+        }
+        let end = if input.peek(syn::Ident) {
+            let to: Ident = input.parse()?;
+            if to != "to" {
+                return Err(Error::new_spanned(to, "expected `to`"));
+            }
+            if input.peek(syn::Ident) {
+                let max: Ident = input.parse()?;
+                if max != "max" {
+                    return Err(Error::new_spanned(max, "expected an integer or `max`"));
+                }
+                MAX_FIELD_NUMBER
+            } else {
+                let lit: LitInt = input.parse()?;
+                let end: u64 = lit.base10_parse()?;
+                if end < start || end > MAX_FIELD_NUMBER {
+                    return Err(Error::new_spanned(
+                        lit,
+                        "reserved range end is out of range",
+                    ));
+                }
+                end
+            }
+        } else {
+            start
+        };
+        Ok(Reserved::Range { start, end })
+    }
+}
+
+/// Parses the message-level `#[zombie(...)]` attributes.
+pub fn extract_message_options(attrs: &[Attribute]) -> Result<MessageOptions> {
+    let mut options = MessageOptions {
+        syntax: Syntax::Proto2,
+        package: String::new(),
+        reserved: Vec::new(),
+        builder: false,
+        debug: false,
+        validate_on_decode: false,
+    };
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("reserved")) {
+        let reserved = attr.parse_args_with(Punctuated::<Reserved, Token![,]>::parse_terminated)?;
+        options.reserved.extend(reserved);
+    }
+
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("zombie")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[zombie(...)]")),
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("syntax") => {
+                    options.syntax = match &nv.lit {
+                        Lit::Str(s) if s.value() == "proto2" => Syntax::Proto2,
+                        Lit::Str(s) if s.value() == "proto3" => Syntax::Proto3,
+                        lit => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "syntax must be \"proto2\" or \"proto3\"",
+                            ))
+                        }
+                    };
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("package") => {
+                    options.package = match &nv.lit {
+                        Lit::Str(s) => s.value(),
+                        lit => return Err(Error::new_spanned(lit, "package must be a string")),
+                    };
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("builder") => {
+                    options.builder = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("debug") => {
+                    options.debug = true;
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("validate_on_decode") => {
+                    options.validate_on_decode = true;
+                }
+                nested => return Err(Error::new_spanned(nested, "unknown zombie attribute")),
+            }
+        }
+    }
+
+    Ok(options)
+}
+
+/// Copies `generics`, requiring every type parameter to implement `bounds`.
+pub fn bounded_generics(generics: &Generics, bounds: TokenStream) -> Generics {
+    let mut bounded = generics.clone();
+    let params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = bounded.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bounds));
+    }
+    bounded
+}
 
-#[derive(Debug)]
 pub struct FieldDesc {
     pub id: u64,
+    pub id_span: Span,
     pub name: Ident,
     pub ty: ProtoType,
+    pub rust_ty: Type,
+    pub optional: bool,
+    pub required: bool,
+    pub default: Option<Expr>,
+    /// The module named by `#[zombie(with = "...")]`, whose `encode`, `decode` and
+    /// `encoded_len` functions stand in for the field type's own traits. The field
+    /// type must still be `Clone`, for merging, and `Debug` if the message derives
+    /// `Diff`.
+    pub with: Option<Path>,
+    /// Marked `#[sensitive]`: shown as `<redacted>` in generated output.
+    pub sensitive: bool,
+    /// The rules of the field's `#[validate(...)]` attributes.
+    pub validate: Vec<ValidationRule>,
 }
 
-pub fn extract_fields(data: DataStruct) -> Result<Vec<FieldDesc>> {
-    let mut fields = Vec::new();
+/// Whether a field holds one value, maybe one value, or a list of them.
+pub(crate) enum Shape {
+    Plain,
+    Optional(Type),
+    Repeated(Type),
+}
 
-    for field in data.fields.iter() {
-        let ident = field
-            .ident
-            .as_ref()
-            .ok_or_else(|| anyhow!("no ident for field"))?
-            .clone();
-
-        let type_attr = field.attrs.iter().find(|attr| attr.path.is_ident("pbtype"));
-        let type_attr = if let Some(attr) = type_attr {
-            let id: Ident = attr.parse_args()?;
-            let s = id.to_string();
-            let pt = ProtoType::from_str(&s).ok_or_else(|| anyhow!("invalid proto type {}", s))?;
-            Some(pt)
-        } else {
-            None
+impl FieldDesc {
+    pub(crate) fn shape(&self) -> Shape {
+        if let Some(inner) = option_inner_type(&self.rust_ty) {
+            return Shape::Optional(inner);
+        }
+        match vec_inner_type(&self.rust_ty) {
+            // Bytes are a single value, not a list of bytes.
+            Some(Type::Path(path)) if path.path.is_ident("u8") => Shape::Plain,
+            Some(inner) => Shape::Repeated(inner),
+            None => Shape::Plain,
+        }
+    }
+
+    /// The declared `#[default(...)]` value, converted to the field's Rust type.
+    pub fn default_value(&self) -> Option<TokenStream> {
+        let value = match self.default.as_ref()? {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }) => quote! { #s.to_owned() },
+            Expr::Lit(ExprLit {
+                lit: Lit::ByteStr(s),
+                ..
+            }) => quote! { #s.to_vec() },
+            expr => quote! { #expr },
         };
+        Some(if self.optional {
+            quote! { Some(#value) }
+        } else {
+            value
+        })
+    }
+
+    /// The value of a `with` field before anything is read, which can't come from
+    /// `ProtoDefault` since the field type needn't implement it.
+    pub fn with_default_value(&self) -> TokenStream {
+        self.default_value()
+            .unwrap_or_else(|| quote! { ::core::default::Default::default() })
+    }
+}
 
-        let type_inferred = infer_proto_type(&field.ty)?;
+/// Checks a field's number and name against protobuf's rules and the message's
+/// reserved numbers and names.
+fn validate_field(
+    field: &FieldDesc,
+    options: &MessageOptions,
+    seen: &mut HashMap<u64, Ident>,
+) -> Result<()> {
+    let id = field.id;
+    if id == 0 {
+        return Err(Error::new(field.id_span, "field number must be at least 1"));
+    }
+    if id > MAX_FIELD_NUMBER {
+        return Err(Error::new(
+            field.id_span,
+            format!("field number must be at most {}", MAX_FIELD_NUMBER),
+        ));
+    }
+    let (start, end) = IMPLEMENTATION_RESERVED;
+    if (start..=end).contains(&id) {
+        return Err(Error::new(
+            field.id_span,
+            format!(
+                "field numbers {} to {} are reserved by protobuf",
+                start, end
+            ),
+        ));
+    }
+    for reserved in options.reserved.iter() {
+        match reserved {
+            Reserved::Range { start, end } if (*start..=*end).contains(&id) => {
+                return Err(Error::new(
+                    field.id_span,
+                    format!("field number {} is reserved", id),
+                ));
+            }
+            Reserved::Name(name) if field.name == name => {
+                return Err(Error::new_spanned(
+                    &field.name,
+                    format!("field name `{}` is reserved", name),
+                ));
+            }
+            _ => {}
+        }
+    }
+    if let Some(other) = seen.get(&id) {
+        return Err(Error::new(
+            field.id_span,
+            format!("field number {} is already used by `{}`", id, other),
+        ));
+    }
+    seen.insert(id, field.name.clone());
+    Ok(())
+}
 
-        let ty = type_attr.unwrap_or(type_inferred);
+/// proto3 has neither required fields nor declared defaults: every field is
+/// optional on the wire and defaults to its type's zero value.
+fn check_syntax(field: &Field, options: &MessageOptions) -> Result<()> {
+    if options.syntax != Syntax::Proto3 {
+        return Ok(());
+    }
+    for attr in field.attrs.iter() {
+        if attr.path.is_ident("required") {
+            return Err(Error::new_spanned(
+                attr,
+                "proto3 has no required fields; use an `Option` field instead",
+            ));
+        }
+        if attr.path.is_ident("default") {
+            return Err(Error::new_spanned(
+                attr,
+                "proto3 fields cannot declare a default; they default to their zero value",
+            ));
+        }
+    }
+    Ok(())
+}
 
-        let id_attr = field
-            .attrs
-            .iter()
-            .find(|attr| attr.path.is_ident("id"))
-            .ok_or_else(|| anyhow!("no id attribute for field {}", ident.to_string()))?;
+pub fn extract_fields(data: &DataStruct, options: &MessageOptions) -> Result<Vec<FieldDesc>> {
+    let named = match &data.fields {
+        Fields::Named(named) => named,
+        Fields::Unnamed(fields) => {
+            return Err(Error::new_spanned(
+                fields,
+                "zombie messages must have named fields",
+            ))
+        }
+        Fields::Unit => {
+            return Err(Error::new(
+                data.struct_token.span,
+                "zombie messages must have named fields",
+            ))
+        }
+    };
 
-        let sid: LitInt = id_attr.parse_args()?;
-        let id: u64 = sid.base10_parse()?;
-        fields.push(FieldDesc {
-            id,
-            name: ident.clone(),
-            ty: ty.clone(),
+    // Report every bad field at once rather than one per compile.
+    let mut fields = Vec::new();
+    let mut errors: Option<Error> = None;
+    let mut seen = HashMap::new();
+    for field in named.named.iter() {
+        let field = extract_field_options(&field.attrs).and_then(|field_options| {
+            if field_options.skip {
+                return Ok(None);
+            }
+            check_syntax(field, options)?;
+            let field = extract_field(field, field_options)?;
+            validate_field(&field, options, &mut seen)?;
+            Ok(Some(field))
         });
+        match field {
+            Ok(Some(field)) => fields.push(field),
+            Ok(None) => {}
+            Err(error) => match errors.as_mut() {
+                Some(errors) => errors.combine(error),
+                None => errors = Some(error),
+            },
+        }
+    }
+
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(fields),
+    }
+}
+
+/// Renders a type for a diagnostic without the spaces `ToTokens` puts around `<` and `>`.
+fn type_name(ty: &Type) -> String {
+    quote!(#ty)
+        .to_string()
+        .replace(" < ", "<")
+        .replace(" >", ">")
+        .replace("& ", "&")
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    with: Option<Path>,
+    skip: bool,
+    skip_default: Option<Path>,
+}
+
+/// Parses the field-level `#[zombie(...)]` attributes.
+fn extract_field_options(attrs: &[Attribute]) -> Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    let mut default_path = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("zombie")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[zombie(...)]")),
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("with") => {
+                    options.with = match &nv.lit {
+                        Lit::Str(s) => Some(s.parse::<Path>()?),
+                        lit => return Err(Error::new_spanned(lit, "with must be a string")),
+                    };
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => {
+                    options.skip = true;
+                }
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("default") => {
+                    options.skip_default = match &nv.lit {
+                        Lit::Str(s) => Some(s.parse::<Path>()?),
+                        lit => return Err(Error::new_spanned(lit, "default must be a string")),
+                    };
+                    default_path = Some(nv.path.clone());
+                }
+                nested => return Err(Error::new_spanned(nested, "unknown zombie attribute")),
+            }
+        }
+    }
+    if let Some(path) = default_path.filter(|_| !options.skip) {
+        return Err(Error::new_spanned(
+            path,
+            "#[zombie(default = \"...\")] is only allowed on skipped fields",
+        ));
+    }
+    if let (true, Some(with)) = (options.skip, &options.with) {
+        return Err(Error::new_spanned(
+            with,
+            "skipped fields are never encoded and can't have a codec",
+        ));
+    }
+    Ok(options)
+}
+
+/// A `#[zombie(skip)]` field: never encoded, and set from `default` (or
+/// `Default::default()`) whenever the message is created for decoding.
+pub struct SkippedField {
+    pub name: Ident,
+    pub default: Option<Path>,
+}
+
+impl SkippedField {
+    pub fn default_value(&self) -> TokenStream {
+        match &self.default {
+            Some(default) => quote! { #default() },
+            None => quote! { ::core::default::Default::default() },
+        }
+    }
+}
+
+pub fn extract_skipped_fields(data: &DataStruct) -> Result<Vec<SkippedField>> {
+    let mut skipped = Vec::new();
+    for field in data.fields.iter() {
+        let options = extract_field_options(&field.attrs)?;
+        if let (true, Some(name)) = (options.skip, &field.ident) {
+            skipped.push(SkippedField {
+                name: name.clone(),
+                default: options.skip_default,
+            });
+        }
     }
+    Ok(skipped)
+}
+
+fn extract_field(field: &Field, options: FieldOptions) -> Result<FieldDesc> {
+    let ident = field
+        .ident
+        .as_ref()
+        .ok_or_else(|| Error::new_spanned(field, "zombie messages must have named fields"))?;
+
+    let with = options.with;
+
+    // A codec module can put any type on the wire as any proto type, so there is
+    // nothing to infer from or check against; it defaults to bytes.
+    let type_inferred = match with {
+        Some(_) => ProtoType::Bytes,
+        None => infer_proto_type(&field.ty)?,
+    };
+
+    let type_attr = field.attrs.iter().find(|attr| attr.path.is_ident("pbtype"));
+    let type_attr = if let Some(attr) = type_attr {
+        let id: Ident = attr.parse_args()?;
+        let pt = ProtoType::from_str(&id.to_string())
+            .ok_or_else(|| Error::new_spanned(&id, format!("unknown pbtype `{}`", id)))?;
+        let compatible = type_inferred.compatible_types();
+        if with.is_none() && !compatible.contains(&pt) {
+            let expected = compatible
+                .iter()
+                .map(|ty| format!("`{}`", ty.name()))
+                .collect::<Vec<_>>();
+            return Err(Error::new_spanned(
+                &id,
+                format!(
+                    "pbtype `{}` is not compatible with field type `{}`; expected {}",
+                    id,
+                    type_name(&field.ty),
+                    expected.join(" or ")
+                ),
+            ));
+        }
+        Some(pt)
+    } else {
+        None
+    };
+
+    let ty = type_attr.unwrap_or(type_inferred);
+
+    let id_attr = field
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("id"))
+        .ok_or_else(|| {
+            Error::new_spanned(
+                ident,
+                format!("missing #[id(...)] attribute on field `{}`", ident),
+            )
+        })?;
+
+    let sid: LitInt = id_attr.parse_args()?;
+    let id: u64 = sid.base10_parse()?;
+
+    let required = field
+        .attrs
+        .iter()
+        .any(|attr| attr.path.is_ident("required"));
+
+    let sensitive = field
+        .attrs
+        .iter()
+        .any(|attr| attr.path.is_ident("sensitive"));
+
+    let default = field
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("default"))
+        .map(|attr| attr.parse_args::<Expr>())
+        .transpose()?;
+
+    let validate = extract_validation_rules(field)?;
 
-    Ok(fields)
+    Ok(FieldDesc {
+        id,
+        id_span: sid.span(),
+        name: ident.clone(),
+        ty,
+        rust_ty: field.ty.clone(),
+        optional: is_option_type(&field.ty),
+        required,
+        default,
+        with,
+        sensitive,
+        validate,
+    })
 }
@@ -1,25 +1,248 @@
 use crate::{
-    descriptor::{extract_fields, FieldDesc},
+    builder::derive_builder,
+    descriptor::{
+        bounded_generics, extract_fields, extract_message_options, extract_skipped_fields,
+        FieldDesc, MessageOptions,
+    },
+    merge::{derive_merge_enum, derive_merge_struct},
     proto_type::{ProtoType, WireType},
+    validate::{derive_validate_struct, ValidationErrors},
 };
 
 use proc_macro2::{Ident, TokenStream};
-use quote::quote;
+use quote::{format_ident, quote};
 use std::{
+    cell::Cell,
     io::{self, ErrorKind, Read},
     string::FromUtf8Error,
 };
-use syn::{Data, DataEnum, DataStruct, DeriveInput};
+use syn::{
+    Data, DataEnum, DataStruct, DeriveInput, Error, Expr, ExprLit, Generics, Lit, Visibility,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum DeserializeError {
-    #[error("io error")]
+    #[error("io error: {0}")]
     IoError(#[from] io::Error),
-    #[error("utf-8 error")]
+    #[error("utf-8 error: {0}")]
     Utf8Error(#[from] FromUtf8Error),
     #[error("type error: `{0}`")]
     TypeError(String),
+    #[error("value {value} out of range for {ty}")]
+    OutOfRange { ty: String, value: String },
+    #[error("expected {expected} bytes for {ty}, got {actual}")]
+    LengthMismatch {
+        ty: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("invalid message: {0}")]
+    Invalid(#[from] ValidationErrors),
+    #[error("messages nested more than {RECURSION_LIMIT} deep")]
+    RecursionLimitExceeded,
+    #[error("missing required fields in {message}: {}", fields.join(", "))]
+    MissingRequired {
+        message: String,
+        fields: Vec<String>,
+    },
+    /// An error inside a message. `message` is the outermost message type, `path` the
+    /// dotted path to the innermost field that failed and `offset` the byte position
+    /// of that field's value, or of the tag if the tag itself couldn't be read.
+    #[error(
+        "failed to decode {}{} at byte {offset}: {source}",
+        location(.message, .path),
+        field_info(.field, .wiretype)
+    )]
+    InMessage {
+        message: String,
+        path: String,
+        field: Option<u64>,
+        wiretype: Option<WireType>,
+        offset: usize,
+        #[source]
+        source: Box<DeserializeError>,
+    },
+}
+
+fn location(message: &str, path: &str) -> String {
+    match (message.is_empty(), path.is_empty()) {
+        (_, true) => message.to_owned(),
+        (true, false) => path.to_owned(),
+        (false, false) if path.starts_with('[') => format!("{}{}", message, path),
+        (false, false) => format!("{}.{}", message, path),
+    }
+}
+
+fn field_info(field: &Option<u64>, wiretype: &Option<WireType>) -> String {
+    match (field, wiretype) {
+        (Some(field), Some(wiretype)) => format!(" (field {}, wire type {:?})", field, wiretype),
+        (Some(field), None) => format!(" (field {})", field),
+        _ => String::new(),
+    }
+}
+
+impl DeserializeError {
+    /// Locates an error from reading the value of field `name`, which started at
+    /// `offset`. Errors from nested messages keep their innermost field and have their
+    /// path and offset made relative to this message.
+    pub fn in_field(
+        self,
+        message: &str,
+        name: &str,
+        field: u64,
+        wiretype: WireType,
+        offset: usize,
+    ) -> Self {
+        match self {
+            DeserializeError::InMessage {
+                path,
+                field: inner_field,
+                wiretype: inner_wiretype,
+                offset: inner_offset,
+                source,
+                ..
+            } => DeserializeError::InMessage {
+                message: message.to_owned(),
+                path: location(name, &path),
+                field: inner_field.or(Some(field)),
+                wiretype: inner_wiretype.or(Some(wiretype)),
+                offset: offset + inner_offset,
+                source,
+            },
+            source => DeserializeError::InMessage {
+                message: message.to_owned(),
+                path: name.to_owned(),
+                field: Some(field),
+                wiretype: Some(wiretype),
+                offset,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// Locates an error that isn't tied to a field, such as a malformed tag.
+    pub fn in_message(self, message: &str, offset: usize) -> Self {
+        DeserializeError::InMessage {
+            message: message.to_owned(),
+            path: String::new(),
+            field: None,
+            wiretype: None,
+            offset,
+            source: Box::new(self),
+        }
+    }
+
+    /// Marks an error as coming from element `index` of a repeated field.
+    pub fn at_index(self, index: usize) -> Self {
+        match self {
+            DeserializeError::InMessage {
+                path,
+                field,
+                wiretype,
+                offset,
+                source,
+                ..
+            } => DeserializeError::InMessage {
+                message: String::new(),
+                path: location(&format!("[{}]", index), &path),
+                field,
+                wiretype,
+                offset,
+                source,
+            },
+            source => DeserializeError::InMessage {
+                message: String::new(),
+                path: format!("[{}]", index),
+                field: None,
+                wiretype: None,
+                offset: 0,
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// Moves the offset of a located error by `n` bytes, e.g. past a length prefix.
+    pub fn shift(self, n: usize) -> Self {
+        match self {
+            DeserializeError::InMessage {
+                message,
+                path,
+                field,
+                wiretype,
+                offset,
+                source,
+            } => DeserializeError::InMessage {
+                message,
+                path,
+                field,
+                wiretype,
+                offset: offset + n,
+                source,
+            },
+            error => error,
+        }
+    }
+}
+
+/// A reader that counts the bytes read through it, so errors can report where in the
+/// buffer they happened.
+pub struct OffsetReader<R> {
+    inner: R,
+    offset: usize,
+}
+
+impl<R: Read> OffsetReader<R> {
+    pub fn new(inner: R) -> Self {
+        OffsetReader { inner, offset: 0 }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// How deeply submessages may nest while decoding, as in the reference implementation.
+/// Without a limit, a few bytes of input per level are enough to overflow the stack.
+pub const RECURSION_LIMIT: usize = 100;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts one level of submessage nesting on this thread until it is dropped.
+/// Anything that decodes a submessage holds one while it does.
+pub struct RecursionGuard(());
+
+impl RecursionGuard {
+    pub fn enter() -> Result<Self, DeserializeError> {
+        DEPTH.with(|depth| {
+            if depth.get() >= RECURSION_LIMIT {
+                return Err(DeserializeError::RecursionLimitExceeded);
+            }
+            depth.set(depth.get() + 1);
+            Ok(RecursionGuard(()))
+        })
+    }
+}
+
+impl Drop for RecursionGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// The number of bytes `n` takes up as a varint.
+pub fn uvarint_len(n: u64) -> usize {
+    (64 - (n | 1).leading_zeros() as usize).div_ceil(7)
 }
 
 pub trait DeserializeField {
@@ -29,10 +252,112 @@ pub trait DeserializeField {
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError>;
+
+    /// Reads one value of a repeated field into `items`. `u8` overrides this so that
+    /// `Vec<u8>` is `bytes`.
+    fn deserialize_repeated(
+        items: &mut Vec<Self>,
+        prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError>
+    where
+        Self: ProtoDefault + Sized,
+    {
+        let mut item = Self::proto_default();
+        item.deserialize_field(prototype, wiretype, r)
+            .map_err(|error| error.at_index(items.len()))?;
+        items.push(item);
+        Ok(())
+    }
 }
 
 pub trait Deserialize {
     fn deserialize(&mut self, r: &mut impl Read) -> Result<(), DeserializeError>;
+
+    /// Reads fields from `r` into this message, following protobuf merge semantics.
+    fn merge_from(&mut self, r: &mut impl Read) -> Result<(), DeserializeError> {
+        self.deserialize(r)
+    }
+
+    /// Checks a message once `decode_from` has read all of it. The derive runs
+    /// `Validate` here for `#[zombie(validate_on_decode)]` messages; submessages and
+    /// merges aren't checked, since they may only be part of a message.
+    fn check_decoded(&self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    /// Reads a whole message from `r` and checks it with `check_decoded`.
+    fn decode_from(r: &mut impl Read) -> Result<Self, DeserializeError>
+    where
+        Self: ProtoDefault + Sized,
+    {
+        let mut message = Self::proto_default();
+        message.deserialize(r)?;
+        message.check_decoded()?;
+        Ok(message)
+    }
+
+    fn decode_from_slice(buf: &[u8]) -> Result<Self, DeserializeError>
+    where
+        Self: ProtoDefault + Sized,
+    {
+        Self::decode_from(&mut &buf[..])
+    }
+}
+
+/// The value a field has before anything is read from the wire: zero scalars,
+/// empty collections, and submessages with all of their fields defaulted.
+pub trait ProtoDefault {
+    fn proto_default() -> Self;
+}
+
+macro_rules! impl_proto_default {
+    ($($ty:ty => $value:expr),* $(,)?) => {
+        $(
+            impl ProtoDefault for $ty {
+                fn proto_default() -> Self {
+                    $value
+                }
+            }
+        )*
+    };
+}
+
+impl_proto_default! {
+    i32 => 0,
+    i64 => 0,
+    u32 => 0,
+    u64 => 0,
+    bool => false,
+    f32 => 0.0,
+    f64 => 0.0,
+    String => String::new(),
+    i8 => 0,
+    i16 => 0,
+    isize => 0,
+    u8 => 0,
+    u16 => 0,
+    usize => 0,
+    char => '\0',
+}
+
+impl<const N: usize> ProtoDefault for [u8; N] {
+    fn proto_default() -> Self {
+        [0; N]
+    }
+}
+
+impl<T> ProtoDefault for Vec<T> {
+    fn proto_default() -> Self {
+        Vec::new()
+    }
+}
+
+impl<T> ProtoDefault for Option<T> {
+    fn proto_default() -> Self {
+        None
+    }
 }
 
 pub fn read_tag(r: &mut impl Read) -> Result<Option<(u64, WireType)>, DeserializeError> {
@@ -56,23 +381,20 @@ fn read_byte(r: &mut impl Read) -> io::Result<u8> {
     Ok(buf[0])
 }
 
+/// Reads a varint of at most ten bytes, the most a `u64` needs.
 pub fn read_uvarint(r: &mut impl Read) -> io::Result<u64> {
     let mut n = 0u64;
-    let mut shift = 0u8;
-    let mut more = true;
-    while more {
-        let mut b = read_byte(r)?;
-        more = (b & 0b10000000) != 0;
-        b = b & 0b01111111;
-
-        // TODO(klimt): Check that shift is valid.
-
-        let m = (b as u64) << shift;
-        shift += 7;
-
-        n = n | m;
+    for shift in (0..MAX_VARINT_LEN as u32).map(|i| i * 7) {
+        let b = read_byte(r)?;
+        n |= ((b & 0b01111111) as u64) << shift;
+        if b & 0b10000000 == 0 {
+            return Ok(n);
+        }
     }
-    Ok(n)
+    Err(io::Error::new(
+        ErrorKind::InvalidData,
+        format!("varint longer than {} bytes", MAX_VARINT_LEN),
+    ))
 }
 
 fn read_ivarint(r: &mut impl Read) -> Result<i64, DeserializeError> {
@@ -154,60 +476,11 @@ pub fn read_u64(r: &mut impl Read, wiretype: WireType) -> Result<u64, Deserializ
     }
 }
 
-fn read_int(
-    r: &mut impl Read,
-    wiretype: WireType,
-    prototype: ProtoType,
-) -> Result<i64, DeserializeError> {
-    match prototype {
-        ProtoType::Int32 | ProtoType::SFixed32 => Ok(read_i32(r, wiretype)? as i64),
-        ProtoType::Int64 | ProtoType::SFixed64 => read_i64(r, wiretype),
-        ProtoType::UInt32 | ProtoType::Fixed32 => Ok(read_u32(r, wiretype)? as i64),
-        // TODO(klimt): Maybe do bounds-checking here instead.
-        ProtoType::UInt64 | ProtoType::Fixed64 => Err(DeserializeError::TypeError(
-            "attempted to read u64 field as signed".to_owned(),
-        )),
-        ProtoType::SInt32 | ProtoType::SInt64 => Ok(decode_zigzag(read_u64(r, wiretype)?)),
-        ProtoType::Bool => read_i64(r, wiretype),
-        ProtoType::Enum => read_i64(r, wiretype),
-        _ => Err(DeserializeError::TypeError(format!(
-            "attempted to read int value for {:?}",
-            prototype
-        ))),
-    }
-}
-
-fn read_uint(
-    r: &mut impl Read,
-    wiretype: WireType,
-    prototype: ProtoType,
-) -> Result<u64, DeserializeError> {
-    match prototype {
-        ProtoType::Int32
-        | ProtoType::SFixed32
-        | ProtoType::Int64
-        | ProtoType::SFixed64
-        | ProtoType::SInt32
-        | ProtoType::SInt64 => Err(DeserializeError::TypeError(format!(
-            "attempted to read unsigned value for {:?}",
-            prototype
-        ))),
-        ProtoType::UInt32 | ProtoType::Fixed32 => Ok(read_u32(r, wiretype)? as u64),
-        ProtoType::UInt64 | ProtoType::Fixed64 => read_u64(r, wiretype),
-        ProtoType::Bool => read_u64(r, wiretype),
-        ProtoType::Enum => read_u64(r, wiretype),
-        _ => Err(DeserializeError::TypeError(format!(
-            "attempted to read uint value for {:?}",
-            prototype
-        ))),
-    }
-}
-
 fn decode_zigzag(n: u64) -> i64 {
     let neg = (n & 1) != 0;
     let mut n = n >> 1;
     if neg {
-        n = n ^ !0;
+        n ^= !0;
     }
     i64::from_le_bytes(n.to_le_bytes())
 }
@@ -219,8 +492,11 @@ impl DeserializeField for i32 {
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        // TODO(klimt): Bounds checking?
-        *self = read_int(r, wiretype, prototype)? as i32;
+        // The derive only allows int32, sint32, sfixed32 and enum here.
+        *self = match prototype {
+            ProtoType::SInt32 => decode_zigzag(read_u64(r, wiretype)?) as i32,
+            _ => read_i32(r, wiretype)?,
+        };
         Ok(())
     }
 }
@@ -232,7 +508,10 @@ impl DeserializeField for i64 {
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        *self = read_int(r, wiretype, prototype)?;
+        *self = match prototype {
+            ProtoType::SInt64 => decode_zigzag(read_u64(r, wiretype)?),
+            _ => read_i64(r, wiretype)?,
+        };
         Ok(())
     }
 }
@@ -240,12 +519,11 @@ impl DeserializeField for i64 {
 impl DeserializeField for u32 {
     fn deserialize_field(
         &mut self,
-        prototype: ProtoType,
+        _prototype: ProtoType,
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        // TODO(klimt): Bounds checking?
-        *self = read_uint(r, wiretype, prototype)? as u32;
+        *self = read_u32(r, wiretype)?;
         Ok(())
     }
 }
@@ -253,25 +531,93 @@ impl DeserializeField for u32 {
 impl DeserializeField for u64 {
     fn deserialize_field(
         &mut self,
-        prototype: ProtoType,
+        _prototype: ProtoType,
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        *self = read_uint(r, wiretype, prototype)?;
+        *self = read_u64(r, wiretype)?;
         Ok(())
     }
 }
 
 impl DeserializeField for bool {
+    fn deserialize_field(
+        &mut self,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        *self = read_u64(r, wiretype)? != 0;
+        Ok(())
+    }
+}
+
+// Narrower integers are read as the protobuf scalar they are widened to on encode,
+// and rejected if the value doesn't fit.
+macro_rules! impl_deserialize_narrowed {
+    ($($ty:ty => $wide:ty),* $(,)?) => {
+        $(
+            impl DeserializeField for $ty {
+                fn deserialize_field(
+                    &mut self,
+                    prototype: ProtoType,
+                    wiretype: WireType,
+                    r: &mut impl Read,
+                ) -> Result<(), DeserializeError> {
+                    let mut wide: $wide = 0;
+                    wide.deserialize_field(prototype, wiretype, r)?;
+                    *self = <$ty>::try_from(wide).map_err(|_| DeserializeError::OutOfRange {
+                        ty: stringify!($ty).to_owned(),
+                        value: wide.to_string(),
+                    })?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_deserialize_narrowed! {
+    i8 => i32,
+    i16 => i32,
+    isize => i64,
+    u16 => u32,
+    usize => u64,
+    char => u32,
+}
+
+impl DeserializeField for u8 {
     fn deserialize_field(
         &mut self,
         prototype: ProtoType,
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        *self = read_uint(r, wiretype, prototype)? != 0;
+        let mut wide: u32 = 0;
+        wide.deserialize_field(prototype, wiretype, r)?;
+        *self = u8::try_from(wide).map_err(|_| DeserializeError::OutOfRange {
+            ty: "u8".to_owned(),
+            value: wide.to_string(),
+        })?;
         Ok(())
     }
+
+    fn deserialize_repeated(
+        items: &mut Vec<Self>,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        if let WireType::Len = wiretype {
+            *items = read_len(r)?;
+            Ok(())
+        } else {
+            Err(DeserializeError::TypeError(format!(
+                "invalid wiretype for Vec<u8>: {:?}",
+                wiretype
+            )))
+        }
+    }
 }
 
 pub fn read_float(r: &mut impl Read, wiretype: WireType) -> Result<f64, DeserializeError> {
@@ -317,14 +663,90 @@ impl DeserializeField for f32 {
     }
 }
 
+/// Reads a length-delimited value. The length comes from the input, so the buffer
+/// only grows as bytes actually arrive.
 pub fn read_len(r: &mut impl Read) -> io::Result<Vec<u8>> {
     let len = read_uvarint(r)?;
-    let len = len as usize;
-    let mut v = vec![0u8; len];
-    r.read_exact(&mut v[..])?;
+    let mut v = Vec::new();
+    r.take(len).read_to_end(&mut v)?;
+    if (v.len() as u64) < len {
+        return Err(io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("expected {} bytes, got {}", len, v.len()),
+        ));
+    }
     Ok(v)
 }
 
+/// The most bytes a varint can take up: ten, for 64 bits at seven bits a byte.
+const MAX_VARINT_LEN: usize = 10;
+
+/// Reads the raw bytes of a field's value, without the length prefix of a
+/// length-delimited field, checking that `wiretype` is the one `pbtype` uses.
+pub fn read_field_value(
+    pbtype: ProtoType,
+    wiretype: WireType,
+    r: &mut impl Read,
+) -> Result<Vec<u8>, DeserializeError> {
+    if wiretype != pbtype.wire_type() {
+        return Err(DeserializeError::TypeError(format!(
+            "invalid wiretype for {}: {:?}",
+            pbtype.name(),
+            wiretype
+        )));
+    }
+    match wiretype {
+        WireType::VarInt => {
+            let mut v = Vec::new();
+            loop {
+                let mut byte = [0u8; 1];
+                r.read_exact(&mut byte)?;
+                v.push(byte[0]);
+                if byte[0] & 0b10000000 == 0 {
+                    break;
+                }
+                if v.len() == MAX_VARINT_LEN {
+                    return Err(DeserializeError::TypeError(format!(
+                        "varint longer than {} bytes",
+                        MAX_VARINT_LEN
+                    )));
+                }
+            }
+            Ok(v)
+        }
+        WireType::I64 => {
+            let mut v = vec![0u8; 8];
+            r.read_exact(&mut v[..])?;
+            Ok(v)
+        }
+        WireType::Len => Ok(read_len(r)?),
+        WireType::I32 => {
+            let mut v = vec![0u8; 4];
+            r.read_exact(&mut v[..])?;
+            Ok(v)
+        }
+    }
+}
+
+/// Reads and discards the value of a field this message doesn't know about.
+pub fn skip_field(wiretype: WireType, r: &mut impl Read) -> Result<(), DeserializeError> {
+    match wiretype {
+        WireType::VarInt => {
+            read_uvarint(r)?;
+        }
+        WireType::I64 => {
+            read_fixed_u64(r)?;
+        }
+        WireType::Len => {
+            read_len(r)?;
+        }
+        WireType::I32 => {
+            read_fixed_u32(r)?;
+        }
+    }
+    Ok(())
+}
+
 impl DeserializeField for String {
     fn deserialize_field(
         &mut self,
@@ -345,7 +767,7 @@ impl DeserializeField for String {
     }
 }
 
-impl DeserializeField for Vec<u8> {
+impl<const N: usize> DeserializeField for [u8; N] {
     fn deserialize_field(
         &mut self,
         _prototype: ProtoType,
@@ -353,100 +775,309 @@ impl DeserializeField for Vec<u8> {
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
         if let WireType::Len = wiretype {
-            *self = read_len(r)?;
+            let v = read_len(r)?;
+            *self = v
+                .try_into()
+                .map_err(|v: Vec<u8>| DeserializeError::LengthMismatch {
+                    ty: format!("[u8; {}]", N),
+                    expected: N,
+                    actual: v.len(),
+                })?;
             Ok(())
         } else {
             Err(DeserializeError::TypeError(format!(
-                "invalid wiretype for Vec<u8>: {:?}",
-                wiretype
+                "invalid wiretype for [u8; {}]: {:?}",
+                N, wiretype
             )))
         }
     }
 }
 
-impl<T: DeserializeField + Default> DeserializeField for Vec<T> {
+impl<T: DeserializeField + ProtoDefault> DeserializeField for Vec<T> {
     fn deserialize_field(
         &mut self,
         prototype: ProtoType,
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        let mut item = T::default();
-        item.deserialize_field(prototype, wiretype, r)?;
-        self.push(item);
-        Ok(())
+        T::deserialize_repeated(self, prototype, wiretype, r)
     }
 }
 
-impl<T: DeserializeField + Default> DeserializeField for Option<T> {
+impl<T: DeserializeField + ProtoDefault> DeserializeField for Option<T> {
     fn deserialize_field(
         &mut self,
         prototype: ProtoType,
         wiretype: WireType,
         r: &mut impl Read,
     ) -> Result<(), DeserializeError> {
-        let mut item: T = T::default();
-        item.deserialize_field(prototype, wiretype, r)?;
-        *self = Some(item);
-        Ok(())
+        // Merge into an existing value so a submessage seen more than once combines.
+        self.get_or_insert_with(T::proto_default)
+            .deserialize_field(prototype, wiretype, r)
     }
 }
 
 impl FieldDesc {
-    fn deserialize_value_clause(&self) -> TokenStream {
+    fn deserialize_value_clause(&self, message: &str) -> TokenStream {
         let ident = &self.name;
+        let name = self.name.to_string();
         let id = self.id;
         let ty = self.ty;
+        let read = match &self.with {
+            Some(with) => {
+                let value = if self.optional {
+                    quote! { Some(value) }
+                } else {
+                    quote! { value }
+                };
+                quote! {
+                    {
+                        let value = zombie::read_field_value(#ty, wiretype, r)
+                            .and_then(|v| #with::decode(&mut &v[..]))
+                            .map_err(|error| error.in_field(#message, #name, #id, wiretype, offset))?;
+                        self.#ident = #value;
+                    }
+                }
+            }
+            None => quote! {
+                self.#ident
+                    .deserialize_field(#ty, wiretype, r)
+                    .map_err(|error| error.in_field(#message, #name, #id, wiretype, offset))?
+            },
+        };
+        if self.required {
+            let has = self.presence_ident();
+            quote! {
+                #id => {
+                    #read;
+                    #has = true;
+                }
+            }
+        } else {
+            quote! {
+                #id => #read
+            }
+        }
+    }
+
+    fn presence_ident(&self) -> Ident {
+        format_ident!("has_{}", self.name)
+    }
+
+    fn deserialize_presence_decl(&self) -> TokenStream {
+        let has = self.presence_ident();
+        quote! {
+            let mut #has = false;
+        }
+    }
+
+    fn proto_default_value(&self) -> TokenStream {
+        let ident = &self.name;
+        if self.with.is_some() {
+            let value = self.with_default_value();
+            quote! { #ident: #value }
+        } else {
+            match self.default_value() {
+                Some(value) => quote! { #ident: #value },
+                None => quote! { #ident: zombie::ProtoDefault::proto_default() },
+            }
+        }
+    }
+
+    fn deserialize_required_check(&self) -> TokenStream {
+        let ident = &self.name;
+        let has = self.presence_ident();
+        let name = self.name.to_string();
+        // An optional field may already have been set before merging.
+        let present = if self.optional {
+            quote! { #has || self.#ident.is_some() }
+        } else {
+            quote! { #has }
+        };
         quote! {
-            #id => self.#ident.deserialize_field(#ty, wiretype, r)?
+            if !(#present) {
+                missing.push(#name.to_owned());
+            }
         }
     }
 }
 
-fn derive_deserialize_struct(name: Ident, data: DataStruct) -> anyhow::Result<TokenStream> {
-    let fields = extract_fields(data)?;
+fn derive_deserialize_struct(
+    name: Ident,
+    vis: Visibility,
+    generics: Generics,
+    options: MessageOptions,
+    data: DataStruct,
+) -> syn::Result<TokenStream> {
+    let fields = extract_fields(&data, &options)?;
+    let skipped = extract_skipped_fields(&data)?;
+
+    let merge = derive_merge_struct(&name, &generics, &options, &fields);
+
+    let builder = if options.builder {
+        derive_builder(&name, &vis, &generics, &fields)
+    } else {
+        quote! {}
+    };
+
+    let default_generics = bounded_generics(&generics, quote! { zombie::ProtoDefault });
+    let (default_impl_generics, _, default_where_clause) = default_generics.split_for_impl();
+
+    let validate = derive_validate_struct(&name, &generics, &fields);
+
+    let (generics, validate_on_decode) = if options.validate_on_decode {
+        let generics = bounded_generics(
+            &generics,
+            quote! { zombie::DeserializeField + zombie::ProtoDefault + zombie::Validate },
+        );
+        let check = quote! {
+            fn check_decoded(&self) -> Result<(), zombie::DeserializeError> {
+                Ok(zombie::Validate::validate(self)?)
+            }
+        };
+        (generics, check)
+    } else {
+        let generics = bounded_generics(
+            &generics,
+            quote! { zombie::DeserializeField + zombie::ProtoDefault },
+        );
+        (generics, quote! {})
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let presence = fields
+        .iter()
+        .filter(|field| field.required)
+        .map(|field| field.deserialize_presence_decl())
+        .collect::<Vec<TokenStream>>();
+
+    let required = fields
+        .iter()
+        .filter(|field| field.required)
+        .map(|field| field.deserialize_required_check())
+        .collect::<Vec<TokenStream>>();
+    let required = if required.is_empty() {
+        quote! {}
+    } else {
+        let message = name.to_string();
+        quote! {
+            let mut missing: Vec<String> = Vec::new();
+            #(#required)*
+            if !missing.is_empty() {
+                return Err(zombie::DeserializeError::MissingRequired {
+                    message: #message.to_owned(),
+                    fields: missing,
+                });
+            }
+        }
+    };
+
+    let field_defaults = fields
+        .iter()
+        .map(|field| field.proto_default_value())
+        .chain(skipped.iter().map(|field| {
+            let ident = &field.name;
+            let value = field.default_value();
+            quote! { #ident: #value }
+        }))
+        .collect::<Vec<TokenStream>>();
 
+    let message = name.to_string();
     let fields = fields
-        .into_iter()
-        .map(|field| field.deserialize_value_clause())
+        .iter()
+        .map(|field| field.deserialize_value_clause(&message))
         .collect::<Vec<TokenStream>>();
 
     let out: TokenStream = quote! {
         #[automatically_derived]
-        impl zombie::DeserializeField for #name {
+        impl #impl_generics zombie::DeserializeField for #name #ty_generics #where_clause {
             fn deserialize_field(
                 &mut self,
                 prototype: zombie::ProtoType,
                 wiretype: zombie::WireType,
                 r: &mut impl std::io::Read
             ) -> Result<(), zombie::DeserializeError> {
-                let len = zombie::read_uvarint(r)?;
-                let len = len as usize;
-                let mut v = vec![0u8; len];
-                r.read_exact(&mut v[..])?;
+                let _guard = zombie::RecursionGuard::enter()?;
+                let v = zombie::read_len(r)?;
+                // Offsets inside the submessage count from the start of this field's value.
                 self.deserialize(&mut &v[..])
+                    .map_err(|error| error.shift(zombie::uvarint_len(v.len() as u64)))
             }
         }
 
-        impl zombie::Deserialize for #name {
+        #[automatically_derived]
+        impl #default_impl_generics zombie::ProtoDefault for #name #ty_generics #default_where_clause {
+            fn proto_default() -> Self {
+                #name {
+                    #(#field_defaults),*
+                }
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics zombie::Deserialize for #name #ty_generics #where_clause {
             fn deserialize(&mut self, r: &mut impl std::io::Read) -> Result<(), zombie::DeserializeError> {
-                while let Some((id, wiretype)) = zombie::read_tag(r)? {
+                let r = &mut zombie::OffsetReader::new(r);
+                #(#presence)*
+                loop {
+                    let start = r.offset();
+                    let (id, wiretype) = match zombie::read_tag(r) {
+                        Ok(Some(tag)) => tag,
+                        Ok(None) => break,
+                        Err(error) => return Err(error.in_message(#message, start)),
+                    };
+                    let offset = r.offset();
                     match id {
-                        #(#fields),*,
-                        _ => {},
+                        #(#fields,)*
+                        _ => zombie::skip_field(wiretype, r)
+                            .map_err(|error| error.in_message(#message, offset))?,
                     }
                 }
+                #required
                 Ok(())
             }
+
+            #validate_on_decode
         }
-    }
-    .into();
+
+        #merge
+
+        #validate
+
+        #builder
+    };
 
     Ok(out)
 }
 
-fn derive_deserialize_enum(name: Ident, _data: DataEnum) -> anyhow::Result<TokenStream> {
+fn derive_deserialize_enum(name: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+    // Proto enums default to their zero value, which proto3 requires to be listed first.
+    let zero = data
+        .variants
+        .iter()
+        .find(|variant| match &variant.discriminant {
+            Some((
+                _,
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(n), ..
+                }),
+            )) => n.base10_digits() == "0",
+            _ => false,
+        })
+        .or_else(|| data.variants.first())
+        .ok_or_else(|| Error::new_spanned(&name, format!("enum {} has no variants", name)))?;
+    let zero = &zero.ident;
+
+    let merge = derive_merge_enum(&name);
+
     let out: TokenStream = quote! {
+        #[automatically_derived]
+        impl zombie::ProtoDefault for #name {
+            fn proto_default() -> Self {
+                #name :: #zero
+            }
+        }
+
         #[automatically_derived]
         impl zombie::DeserializeField for #name {
             fn deserialize_field(
@@ -460,17 +1091,24 @@ fn derive_deserialize_enum(name: Ident, _data: DataEnum) -> anyhow::Result<Token
                 Ok(())
             }
         }
-    }
-    .into();
+
+        #merge
+    };
 
     Ok(out)
 }
 
-pub fn derive_deserialize(input: DeriveInput) -> anyhow::Result<TokenStream> {
+pub fn derive_deserialize(input: DeriveInput) -> syn::Result<TokenStream> {
     match input.data {
-        Data::Struct(data) => derive_deserialize_struct(input.ident, data),
+        Data::Struct(data) => {
+            let options = extract_message_options(&input.attrs)?;
+            derive_deserialize_struct(input.ident, input.vis, input.generics, options, data)
+        }
         Data::Enum(data) => derive_deserialize_enum(input.ident, data),
-        _ => panic!("![derive(Deserialize)] only works on structs and enums"),
+        Data::Union(data) => Err(Error::new(
+            data.union_token.span,
+            "#[derive(Deserialize)] only works on structs and enums",
+        )),
     }
 }
 
@@ -478,6 +1116,14 @@ pub fn derive_deserialize(input: DeriveInput) -> anyhow::Result<TokenStream> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn uvarint_len_works() {
+        assert_eq!(1, uvarint_len(0));
+        assert_eq!(1, uvarint_len(127));
+        assert_eq!(2, uvarint_len(128));
+        assert_eq!(10, uvarint_len(u64::MAX));
+    }
+
     #[test]
     fn uvarint_deserialize_zero() {
         let buf: Vec<u8> = vec![0];
@@ -485,6 +1131,28 @@ mod tests {
         assert_eq!(0, n);
     }
 
+    #[test]
+    fn uvarint_deserialize_max() {
+        let buf = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(u64::MAX, read_uvarint(&mut &buf[..]).unwrap());
+    }
+
+    #[test]
+    fn uvarint_deserialize_too_long() {
+        let buf = [0x80u8; 11];
+        let error = read_uvarint(&mut &buf[..]).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, error.kind());
+    }
+
+    #[test]
+    fn read_len_past_the_input() {
+        let buf = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x00,
+        ];
+        let error = read_len(&mut &buf[..]).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, error.kind());
+    }
+
     #[test]
     fn uvarint_deserialize_one() {
         let buf: Vec<u8> = vec![1];
@@ -526,7 +1194,7 @@ mod tests {
 
     #[test]
     fn read_exact_into_vec() {
-        let src = vec![5u8, 4, 3, 2, 1, 0];
+        let src = [5u8, 4, 3, 2, 1, 0];
         let mut dst = vec![0u8; 4];
         (&src[..]).read_exact(&mut dst[..]).unwrap();
         assert_eq!(vec![5u8, 4, 3, 2], dst);
@@ -539,4 +1207,17 @@ mod tests {
         assert_eq!(9, id);
         assert_eq!(WireType::Len as i32, wiretype as i32);
     }
+
+    #[test]
+    fn read_field_value_caps_varints() {
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let v = read_field_value(ProtoType::UInt64, WireType::VarInt, &mut &max[..]).unwrap();
+        assert_eq!(&max[..], &v[..]);
+
+        let endless = [0xffu8; 64];
+        assert!(matches!(
+            read_field_value(ProtoType::UInt64, WireType::VarInt, &mut &endless[..]),
+            Err(DeserializeError::TypeError(_))
+        ));
+    }
 }
@@ -0,0 +1,368 @@
+use crate::descriptor::{bounded_generics, extract_fields, extract_message_options, FieldDesc};
+use crate::redact::{redacting, REDACTED};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+use syn::{Data, DataEnum, DeriveInput, Error, Generics, Result};
+
+/// One field that differs between two messages, identified by its path from the root
+/// message, e.g. `submessage.int32` or `repeated[2]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldChange {
+    Added {
+        path: String,
+        value: String,
+    },
+    Removed {
+        path: String,
+        value: String,
+    },
+    Changed {
+        path: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldChange::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            FieldChange::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            FieldChange::Changed { path, from, to } => write!(f, "{}: {} -> {}", path, from, to),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageDiff {
+    pub changes: Vec<FieldChange>,
+}
+
+impl MessageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn added(&mut self, path: &str, value: String) {
+        let path = path.to_owned();
+        self.changes.push(FieldChange::Added { path, value });
+    }
+
+    pub fn removed(&mut self, path: &str, value: String) {
+        let path = path.to_owned();
+        self.changes.push(FieldChange::Removed { path, value });
+    }
+
+    pub fn changed(&mut self, path: &str, from: String, to: String) {
+        let path = path.to_owned();
+        self.changes.push(FieldChange::Changed { path, from, to });
+    }
+
+    /// Adds the changes found in a `#[sensitive]` field, with their values redacted
+    /// unless inside [`unredacted`](crate::redact::unredacted).
+    pub fn append_sensitive(&mut self, other: MessageDiff) {
+        let redact = redacting();
+        self.changes
+            .extend(other.changes.into_iter().map(|change| match change {
+                _ if !redact => change,
+                FieldChange::Added { path, .. } => FieldChange::Added {
+                    path,
+                    value: REDACTED.to_owned(),
+                },
+                FieldChange::Removed { path, .. } => FieldChange::Removed {
+                    path,
+                    value: REDACTED.to_owned(),
+                },
+                FieldChange::Changed { path, .. } => FieldChange::Changed {
+                    path,
+                    from: REDACTED.to_owned(),
+                    to: REDACTED.to_owned(),
+                },
+            }));
+    }
+}
+
+impl fmt::Display for MessageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in self.changes.iter() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends a field name to a diff path.
+pub fn field_path(path: &str, field: &str) -> String {
+    if path.is_empty() {
+        field.to_owned()
+    } else {
+        format!("{}.{}", path, field)
+    }
+}
+
+/// Field-by-field comparison of messages, implemented by `#[derive(Diff)]`. Every
+/// field type of a derived message must implement it too, except codec fields, which
+/// are compared by their `Debug` output.
+pub trait Diff {
+    /// Renders the value as it appears in a diff line.
+    fn describe(&self) -> String;
+
+    /// Records how `other` differs from `self`, with paths relative to `path`.
+    /// Scalars compare their descriptions; messages and collections recurse.
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        let (from, to) = (self.describe(), other.describe());
+        if from != to {
+            diff.changed(path, from, to);
+        }
+    }
+
+    /// Records the differences between two repeated fields element by element. `u8`
+    /// overrides this so that `Vec<u8>` compares as a single `bytes` value.
+    fn diff_repeated(items: &[Self], other: &[Self], path: &str, diff: &mut MessageDiff)
+    where
+        Self: Sized,
+    {
+        for i in 0..items.len().max(other.len()) {
+            let path = format!("{}[{}]", path, i);
+            match (items.get(i), other.get(i)) {
+                (Some(a), Some(b)) => a.diff_field(b, &path, diff),
+                (Some(a), None) => diff.removed(&path, a.describe()),
+                (None, Some(b)) => diff.added(&path, b.describe()),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn diff(&self, other: &Self) -> MessageDiff {
+        let mut diff = MessageDiff::default();
+        self.diff_field(other, "", &mut diff);
+        diff
+    }
+}
+
+macro_rules! impl_diff_debug {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Diff for $ty {
+                fn describe(&self) -> String {
+                    format!("{:?}", self)
+                }
+            }
+        )*
+    };
+}
+
+impl_diff_debug!(i32, i64, u32, u64, bool, f32, f64);
+impl_diff_debug!(i8, i16, isize, u16, usize, char);
+impl_diff_debug!(String, str, [u8]);
+impl_diff_debug!(SystemTime, Duration);
+
+impl Diff for u8 {
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn diff_repeated(items: &[Self], other: &[Self], path: &str, diff: &mut MessageDiff) {
+        items.diff_field(other, path, diff)
+    }
+}
+
+impl<const N: usize> Diff for [u8; N] {
+    fn describe(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+impl<T: Diff + ?Sized> Diff for &T {
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        (**self).diff_field(*other, path, diff)
+    }
+}
+
+impl<T: Diff> Diff for Vec<T> {
+    fn describe(&self) -> String {
+        let items = self.iter().map(Diff::describe).collect::<Vec<_>>();
+        format!("[{}]", items.join(", "))
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        T::diff_repeated(self, other, path, diff)
+    }
+}
+
+impl<T: Diff> Diff for Option<T> {
+    fn describe(&self) -> String {
+        match self {
+            Some(val) => val.describe(),
+            None => "None".to_owned(),
+        }
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        match (self, other) {
+            (Some(a), Some(b)) => a.diff_field(b, path, diff),
+            (Some(a), None) => diff.removed(path, a.describe()),
+            (None, Some(b)) => diff.added(path, b.describe()),
+            (None, None) => {}
+        }
+    }
+}
+
+fn derive_diff_struct(name: &Ident, generics: &Generics, fields: &[FieldDesc]) -> TokenStream {
+    let generics = bounded_generics(generics, quote! { zombie::Diff });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let labels = fields
+        .iter()
+        .map(|field| field.name.to_string())
+        .collect::<Vec<_>>();
+    let describes = fields
+        .iter()
+        .map(|field| field.describe_call())
+        .collect::<Vec<_>>();
+    let diffs = fields
+        .iter()
+        .map(|field| field.diff_field_call())
+        .collect::<Vec<_>>();
+    let message = name.to_string();
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics zombie::Diff for #name #ty_generics #where_clause {
+            fn describe(&self) -> String {
+                let fields: Vec<String> = vec![
+                    #(format!("{}: {}", #labels, #describes),)*
+                ];
+                if fields.is_empty() {
+                    format!("{} {{}}", #message)
+                } else {
+                    format!("{} {{ {} }}", #message, fields.join(", "))
+                }
+            }
+
+            fn diff_field(&self, other: &Self, path: &str, diff: &mut zombie::MessageDiff) {
+                #(#diffs)*
+            }
+        }
+    }
+}
+
+impl FieldDesc {
+    // Codec fields needn't implement `Diff`, so they are shown with `Debug` instead.
+    fn describe_call(&self) -> TokenStream {
+        let ident = &self.name;
+        let describe = match self.with {
+            Some(_) => quote! { format!("{:?}", self.#ident) },
+            None => quote! { zombie::Diff::describe(&self.#ident) },
+        };
+        if self.sensitive {
+            quote! {
+                if zombie::redacting() {
+                    zombie::REDACTED.to_owned()
+                } else {
+                    #describe
+                }
+            }
+        } else {
+            describe
+        }
+    }
+
+    fn diff_field_call(&self) -> TokenStream {
+        let diff = self.diff_field_values_call();
+        if self.sensitive {
+            // Changes are still reported, just without the values.
+            quote! {
+                let mut field_diff = zombie::MessageDiff::default();
+                {
+                    let diff = &mut field_diff;
+                    #diff
+                }
+                diff.append_sensitive(field_diff);
+            }
+        } else {
+            diff
+        }
+    }
+
+    fn diff_field_values_call(&self) -> TokenStream {
+        let ident = &self.name;
+        let label = ident.to_string();
+        match self.with {
+            Some(_) => quote! {
+                let (from, to) = (format!("{:?}", self.#ident), format!("{:?}", other.#ident));
+                if from != to {
+                    diff.changed(&zombie::field_path(path, #label), from, to);
+                }
+            },
+            None => quote! {
+                zombie::Diff::diff_field(
+                    &self.#ident,
+                    &other.#ident,
+                    &zombie::field_path(path, #label),
+                    diff,
+                );
+            },
+        }
+    }
+}
+
+fn derive_diff_enum(name: &Ident, data: &DataEnum) -> TokenStream {
+    let variants = data.variants.iter().map(|variant| &variant.ident);
+    quote! {
+        #[automatically_derived]
+        impl zombie::Diff for #name {
+            fn describe(&self) -> String {
+                match self {
+                    #(#name :: #variants => stringify!(#variants).to_owned(),)*
+                }
+            }
+        }
+    }
+}
+
+pub fn derive_diff(input: DeriveInput) -> Result<TokenStream> {
+    match input.data {
+        Data::Struct(data) => {
+            let options = extract_message_options(&input.attrs)?;
+            let fields = extract_fields(&data, &options)?;
+            Ok(derive_diff_struct(&input.ident, &input.generics, &fields))
+        }
+        Data::Enum(data) => Ok(derive_diff_enum(&input.ident, &data)),
+        Data::Union(data) => Err(Error::new(
+            data.union_token.span,
+            "#[derive(Diff)] only works on structs and enums",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_diff_reports_index() {
+        let diff = vec![1, 2, 158].diff(&vec![1, 3, 159, 160]);
+        assert_eq!(
+            diff.to_string(),
+            "[1]: 2 -> 3\n[2]: 158 -> 159\n+ [3]: 160\n"
+        );
+    }
+
+    #[test]
+    fn option_diff_reports_presence() {
+        assert_eq!(
+            Some(1).diff(&None).changes,
+            vec![FieldChange::Removed {
+                path: "".to_owned(),
+                value: "1".to_owned()
+            }]
+        );
+        assert!(None::<i32>.diff(&None).is_empty());
+    }
+}
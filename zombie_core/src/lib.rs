@@ -1,4 +1,14 @@
+pub mod builder;
 mod descriptor;
 pub mod deserialize;
+pub mod diff;
+pub mod merge;
+pub mod message;
+pub mod name;
+pub mod pointer;
 pub mod proto_type;
+pub mod redact;
 pub mod serialize;
+pub mod time;
+pub mod validate;
+pub mod wire;
@@ -0,0 +1,331 @@
+use crate::{
+    descriptor::{bounded_generics, FieldDesc, MessageOptions, Syntax},
+    deserialize::ProtoDefault,
+};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::Generics;
+
+/// Protobuf merge semantics for a single field: scalars take the other value,
+/// repeated fields append, and submessages merge recursively.
+pub trait MergeField {
+    fn merge_field(&mut self, other: &Self);
+    fn clear_field(&mut self);
+
+    /// Whether `path` names a field nested inside this one. Only messages have any.
+    fn is_valid_subpath(_path: &str) -> bool {
+        false
+    }
+
+    /// Trims this field down to the nested `paths`.
+    fn apply_subpaths(&mut self, _paths: &[&str]) {}
+
+    /// Replaces the nested `paths` of this field with those from `other`.
+    fn merge_subpaths(&mut self, _other: &Self, _paths: &[&str]) {}
+
+    /// Merges a repeated field by appending. `u8` overrides this so that `Vec<u8>`
+    /// is replaced like any other `bytes` value.
+    fn merge_repeated(items: &mut Vec<Self>, other: &[Self])
+    where
+        Self: ProtoDefault + Sized,
+    {
+        for item in other.iter() {
+            let mut copy = Self::proto_default();
+            copy.merge_field(item);
+            items.push(copy);
+        }
+    }
+}
+
+/// Finds the paths in a field mask that refer to `field`. Returns `None` if the
+/// field isn't mentioned, an empty list if it's named as a whole, and otherwise
+/// the remainders of the paths that descend into it.
+pub fn field_subpaths<'a>(paths: &[&'a str], field: &str) -> Option<Vec<&'a str>> {
+    let mut found = false;
+    let mut subpaths = Vec::new();
+    for path in paths.iter() {
+        match path.split_once('.') {
+            None if *path == field => return Some(Vec::new()),
+            Some((head, rest)) if head == field => {
+                found = true;
+                subpaths.push(rest);
+            }
+            _ => {}
+        }
+    }
+    found.then_some(subpaths)
+}
+
+macro_rules! impl_merge_field {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl MergeField for $ty {
+                fn merge_field(&mut self, other: &Self) {
+                    self.clone_from(other);
+                }
+
+                fn clear_field(&mut self) {
+                    *self = Self::proto_default();
+                }
+            }
+        )*
+    };
+}
+
+impl_merge_field!(i32, i64, u32, u64, bool, f32, f64, String);
+impl_merge_field!(i8, i16, isize, u16, usize, char);
+
+impl MergeField for u8 {
+    fn merge_field(&mut self, other: &Self) {
+        *self = *other;
+    }
+
+    fn clear_field(&mut self) {
+        *self = 0;
+    }
+
+    fn merge_repeated(items: &mut Vec<Self>, other: &[Self]) {
+        items.clear();
+        items.extend_from_slice(other);
+    }
+}
+
+impl<const N: usize> MergeField for [u8; N] {
+    fn merge_field(&mut self, other: &Self) {
+        *self = *other;
+    }
+
+    fn clear_field(&mut self) {
+        *self = [0; N];
+    }
+}
+
+impl<T: MergeField + ProtoDefault> MergeField for Vec<T> {
+    fn merge_field(&mut self, other: &Self) {
+        T::merge_repeated(self, other);
+    }
+
+    fn clear_field(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: MergeField + ProtoDefault> MergeField for Option<T> {
+    fn merge_field(&mut self, other: &Self) {
+        if let Some(other) = other {
+            self.get_or_insert_with(T::proto_default).merge_field(other);
+        }
+    }
+
+    fn clear_field(&mut self) {
+        *self = None;
+    }
+
+    fn is_valid_subpath(path: &str) -> bool {
+        T::is_valid_subpath(path)
+    }
+
+    fn apply_subpaths(&mut self, paths: &[&str]) {
+        if let Some(value) = self {
+            value.apply_subpaths(paths);
+        }
+    }
+
+    fn merge_subpaths(&mut self, other: &Self, paths: &[&str]) {
+        match other {
+            Some(other) => self
+                .get_or_insert_with(T::proto_default)
+                .merge_subpaths(other, paths),
+            None => {
+                if let Some(value) = self {
+                    value.merge_subpaths(&T::proto_default(), paths);
+                }
+            }
+        }
+    }
+}
+
+impl FieldDesc {
+    fn merge_field_call(&self, options: &MessageOptions) -> TokenStream {
+        let ident = &self.name;
+        // Codec fields are opaque, so they merge like scalars: a set value replaces.
+        if self.with.is_some() {
+            return if self.optional {
+                quote! {
+                    if other.#ident.is_some() {
+                        self.#ident = other.#ident.clone();
+                    }
+                }
+            } else {
+                quote! {
+                    self.#ident = other.#ident.clone();
+                }
+            };
+        }
+        if options.syntax == Syntax::Proto3 && !self.optional {
+            quote! {
+                if !zombie::Serialize::is_default(&other.#ident) {
+                    zombie::MergeField::merge_field(&mut self.#ident, &other.#ident);
+                }
+            }
+        } else {
+            quote! {
+                zombie::MergeField::merge_field(&mut self.#ident, &other.#ident);
+            }
+        }
+    }
+}
+
+impl FieldDesc {
+    fn valid_subpath_clause(&self) -> TokenStream {
+        let name = self.name.to_string();
+        let ty = &self.rust_ty;
+        if self.with.is_some() {
+            return quote! {
+                #name => rest.is_none()
+            };
+        }
+        quote! {
+            #name => rest.map_or(true, <#ty as zombie::MergeField>::is_valid_subpath)
+        }
+    }
+
+    fn apply_subpaths_call(&self) -> TokenStream {
+        let ident = &self.name;
+        let name = ident.to_string();
+        if self.with.is_some() {
+            let default = self.with_default_value();
+            return quote! {
+                if zombie::field_subpaths(paths, #name).is_none() {
+                    self.#ident = #default;
+                }
+            };
+        }
+        quote! {
+            match zombie::field_subpaths(paths, #name) {
+                None => zombie::MergeField::clear_field(&mut self.#ident),
+                Some(subpaths) if subpaths.is_empty() => {}
+                Some(subpaths) => zombie::MergeField::apply_subpaths(&mut self.#ident, &subpaths),
+            }
+        }
+    }
+
+    fn merge_subpaths_call(&self) -> TokenStream {
+        let ident = &self.name;
+        let name = ident.to_string();
+        if self.with.is_some() {
+            return quote! {
+                if zombie::field_subpaths(paths, #name).is_some() {
+                    self.#ident = other.#ident.clone();
+                }
+            };
+        }
+        quote! {
+            match zombie::field_subpaths(paths, #name) {
+                None => {}
+                Some(subpaths) if subpaths.is_empty() => {
+                    zombie::MergeField::clear_field(&mut self.#ident);
+                    zombie::MergeField::merge_field(&mut self.#ident, &other.#ident);
+                }
+                Some(subpaths) => {
+                    zombie::MergeField::merge_subpaths(&mut self.#ident, &other.#ident, &subpaths)
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn derive_merge_struct(
+    name: &Ident,
+    generics: &Generics,
+    options: &MessageOptions,
+    fields: &[FieldDesc],
+) -> TokenStream {
+    let generics = bounded_generics(
+        generics,
+        quote! { zombie::MergeField + zombie::ProtoDefault },
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let merges = fields
+        .iter()
+        .map(|field| field.merge_field_call(options))
+        .collect::<Vec<TokenStream>>();
+
+    let valid_subpaths = fields
+        .iter()
+        .map(|field| field.valid_subpath_clause())
+        .collect::<Vec<TokenStream>>();
+
+    let apply_subpaths = fields
+        .iter()
+        .map(|field| field.apply_subpaths_call())
+        .collect::<Vec<TokenStream>>();
+
+    let merge_subpaths = fields
+        .iter()
+        .map(|field| field.merge_subpaths_call())
+        .collect::<Vec<TokenStream>>();
+
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics zombie::MergeField for #name #ty_generics #where_clause {
+            fn merge_field(&mut self, other: &Self) {
+                #(#merges)*
+            }
+
+            fn clear_field(&mut self) {
+                *self = zombie::ProtoDefault::proto_default();
+            }
+
+            fn is_valid_subpath(path: &str) -> bool {
+                let (head, rest) = match path.split_once('.') {
+                    Some((head, rest)) => (head, Some(rest)),
+                    None => (path, None),
+                };
+                match head {
+                    #(#valid_subpaths,)*
+                    _ => false,
+                }
+            }
+
+            fn apply_subpaths(&mut self, paths: &[&str]) {
+                #(#apply_subpaths)*
+            }
+
+            fn merge_subpaths(&mut self, other: &Self, paths: &[&str]) {
+                #(#merge_subpaths)*
+            }
+        }
+    }
+}
+
+pub(crate) fn derive_merge_enum(name: &Ident) -> TokenStream {
+    quote! {
+        #[automatically_derived]
+        impl zombie::MergeField for #name {
+            fn merge_field(&mut self, other: &Self) {
+                *self = other.clone();
+            }
+
+            fn clear_field(&mut self) {
+                *self = zombie::ProtoDefault::proto_default();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_subpaths_works() {
+        let paths = ["a", "b.c", "b.d.e", "bb"];
+        assert_eq!(Some(vec![]), field_subpaths(&paths, "a"));
+        assert_eq!(Some(vec!["c", "d.e"]), field_subpaths(&paths, "b"));
+        assert_eq!(None, field_subpaths(&paths, "c"));
+        assert_eq!(Some(vec![]), field_subpaths(&["b.c", "b"], "b"));
+    }
+}
@@ -0,0 +1,68 @@
+use crate::{
+    deserialize::{Deserialize, DeserializeError, ProtoDefault},
+    merge::MergeField,
+    serialize::Serialize,
+};
+
+use std::io::{self, Write};
+
+/// The everyday API of a message, for any type deriving both `Serialize` and
+/// `Deserialize`. The traits behind it stay available for working field by field;
+/// to decode from an `impl Read` rather than a slice, use `Deserialize::decode_from`.
+pub trait Message: Sized {
+    fn encode(&self, w: &mut impl Write) -> io::Result<()>;
+
+    /// Fails only if a required field is missing.
+    fn encode_to_vec(&self) -> io::Result<Vec<u8>> {
+        let mut v = Vec::new();
+        self.encode(&mut v)?;
+        Ok(v)
+    }
+
+    /// The number of bytes `encode` writes. Fails just as `encode` would.
+    fn encoded_len(&self) -> io::Result<usize> {
+        let mut counter = ByteCounter(0);
+        self.encode(&mut counter)?;
+        Ok(counter.0)
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, DeserializeError>;
+
+    /// Merges `other` into this message: set scalars replace, repeated fields append
+    /// and submessages merge recursively.
+    fn merge(&mut self, other: &Self);
+
+    /// Resets every field to its default.
+    fn clear(&mut self);
+}
+
+impl<T: Serialize + Deserialize + MergeField + ProtoDefault> Message for T {
+    fn encode(&self, w: &mut impl Write) -> io::Result<()> {
+        self.serialize(w)
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, DeserializeError> {
+        T::decode_from_slice(buf)
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.merge_field(other);
+    }
+
+    fn clear(&mut self) {
+        self.clear_field();
+    }
+}
+
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use crate::descriptor::{extract_message_options, MessageOptions};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Generics, Result};
+
+pub const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// The fully-qualified protobuf name of a message, as used in `google.protobuf.Any`.
+/// `#[derive(Name)]` takes the package from `#[zombie(package = "...")]`.
+pub trait Name {
+    const NAME: &'static str;
+    const PACKAGE: &'static str;
+
+    fn full_name() -> String {
+        if Self::PACKAGE.is_empty() {
+            Self::NAME.to_owned()
+        } else {
+            format!("{}.{}", Self::PACKAGE, Self::NAME)
+        }
+    }
+
+    fn type_url() -> String {
+        format!("{}{}", TYPE_URL_PREFIX, Self::full_name())
+    }
+}
+
+/// The `Name::full_name` of a message, for code generated before `Name` is known
+/// to be implemented.
+pub(crate) fn full_name(name: &Ident, options: &MessageOptions) -> String {
+    if options.package.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", options.package, name)
+    }
+}
+
+fn derive_name_struct(name: &Ident, generics: &Generics, options: &MessageOptions) -> TokenStream {
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let message = name.to_string();
+    let package = &options.package;
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics zombie::Name for #name #ty_generics #where_clause {
+            const NAME: &'static str = #message;
+            const PACKAGE: &'static str = #package;
+        }
+    }
+}
+
+pub fn derive_name(input: DeriveInput) -> Result<TokenStream> {
+    match input.data {
+        Data::Struct(_) => {
+            let options = extract_message_options(&input.attrs)?;
+            Ok(derive_name_struct(&input.ident, &input.generics, &options))
+        }
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "#[derive(Name)] only works on structs",
+        )),
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    deserialize::{DeserializeError, DeserializeField, ProtoDefault},
+    diff::{Diff, MessageDiff},
+    merge::MergeField,
+    proto_type::{ProtoType, WireType},
+    serialize::Serialize,
+    wire::Descriptor,
+};
+
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    rc::Rc,
+    sync::Arc,
+};
+
+// Smart pointers are transparent on the wire: they encode exactly like the value
+// they point to, which is what lets messages contain themselves via `Box`.
+macro_rules! impl_pointer_read {
+    ($($ptr:ident),* $(,)?) => {
+        $(
+            impl<T: Serialize + ?Sized> Serialize for $ptr<T> {
+                fn descriptor() -> Option<Descriptor> {
+                    T::descriptor()
+                }
+
+                fn serialize_field(
+                    &self,
+                    id: u64,
+                    pbtype: ProtoType,
+                    w: &mut impl Write,
+                ) -> io::Result<()> {
+                    (**self).serialize_field(id, pbtype, w)
+                }
+
+                fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+                    (**self).serialize(w)
+                }
+
+                fn is_default(&self) -> bool {
+                    (**self).is_default()
+                }
+            }
+
+            impl<T: ProtoDefault> ProtoDefault for $ptr<T> {
+                fn proto_default() -> Self {
+                    $ptr::new(T::proto_default())
+                }
+            }
+
+            impl<T: Diff + ?Sized> Diff for $ptr<T> {
+                fn describe(&self) -> String {
+                    (**self).describe()
+                }
+
+                fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+                    (**self).diff_field(&**other, path, diff)
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer_read!(Box, Rc, Arc);
+
+impl<T: DeserializeField> DeserializeField for Box<T> {
+    fn deserialize_field(
+        &mut self,
+        prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        (**self).deserialize_field(prototype, wiretype, r)
+    }
+}
+
+impl<T: MergeField> MergeField for Box<T> {
+    fn merge_field(&mut self, other: &Self) {
+        (**self).merge_field(other)
+    }
+
+    fn clear_field(&mut self) {
+        (**self).clear_field()
+    }
+
+    fn is_valid_subpath(path: &str) -> bool {
+        T::is_valid_subpath(path)
+    }
+
+    fn apply_subpaths(&mut self, paths: &[&str]) {
+        (**self).apply_subpaths(paths)
+    }
+
+    fn merge_subpaths(&mut self, other: &Self, paths: &[&str]) {
+        (**self).merge_subpaths(other, paths)
+    }
+}
+
+// Shared pointers are copied on write, so other holders never see the change.
+macro_rules! impl_shared_pointer_write {
+    ($($ptr:ident),* $(,)?) => {
+        $(
+            impl<T: DeserializeField + Clone> DeserializeField for $ptr<T> {
+                fn deserialize_field(
+                    &mut self,
+                    prototype: ProtoType,
+                    wiretype: WireType,
+                    r: &mut impl Read,
+                ) -> Result<(), DeserializeError> {
+                    $ptr::make_mut(self).deserialize_field(prototype, wiretype, r)
+                }
+            }
+
+            impl<T: MergeField + Clone> MergeField for $ptr<T> {
+                fn merge_field(&mut self, other: &Self) {
+                    $ptr::make_mut(self).merge_field(other)
+                }
+
+                fn clear_field(&mut self) {
+                    $ptr::make_mut(self).clear_field()
+                }
+
+                fn is_valid_subpath(path: &str) -> bool {
+                    T::is_valid_subpath(path)
+                }
+
+                fn apply_subpaths(&mut self, paths: &[&str]) {
+                    $ptr::make_mut(self).apply_subpaths(paths)
+                }
+
+                fn merge_subpaths(&mut self, other: &Self, paths: &[&str]) {
+                    $ptr::make_mut(self).merge_subpaths(other, paths)
+                }
+            }
+        )*
+    };
+}
+
+impl_shared_pointer_write!(Rc, Arc);
+
+impl<B: Serialize + ToOwned + ?Sized> Serialize for Cow<'_, B> {
+    fn descriptor() -> Option<Descriptor> {
+        B::descriptor()
+    }
+
+    fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        (**self).serialize_field(id, pbtype, w)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        (**self).serialize(w)
+    }
+
+    fn is_default(&self) -> bool {
+        (**self).is_default()
+    }
+}
+
+impl<B: ToOwned + ?Sized> DeserializeField for Cow<'_, B>
+where
+    B::Owned: DeserializeField,
+{
+    fn deserialize_field(
+        &mut self,
+        prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        self.to_mut().deserialize_field(prototype, wiretype, r)
+    }
+}
+
+impl<B: ToOwned + ?Sized> ProtoDefault for Cow<'_, B>
+where
+    B::Owned: ProtoDefault,
+{
+    fn proto_default() -> Self {
+        Cow::Owned(B::Owned::proto_default())
+    }
+}
+
+impl<B: ToOwned + ?Sized> MergeField for Cow<'_, B>
+where
+    B::Owned: MergeField,
+{
+    fn merge_field(&mut self, other: &Self) {
+        let other: B::Owned = (**other).to_owned();
+        self.to_mut().merge_field(&other)
+    }
+
+    fn clear_field(&mut self) {
+        self.to_mut().clear_field()
+    }
+}
+
+impl<B: Diff + ToOwned + ?Sized> Diff for Cow<'_, B> {
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn diff_field(&self, other: &Self, path: &str, diff: &mut MessageDiff) {
+        (**self).diff_field(&**other, path, diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_pointer_copies_on_write() {
+        let shared = Rc::new(7i32);
+        let mut field = shared.clone();
+        field
+            .deserialize_field(ProtoType::Int32, WireType::VarInt, &mut &[0x2a][..])
+            .unwrap();
+        assert_eq!(42, *field);
+        assert_eq!(7, *shared);
+    }
+
+    #[test]
+    fn cow_round_trip() {
+        let borrowed: Cow<str> = Cow::Borrowed("hi");
+        let mut v = Vec::new();
+        borrowed
+            .serialize_field(1, ProtoType::String, &mut v)
+            .unwrap();
+        assert_eq!(v, vec![0x0a, 0x02, b'h', b'i']);
+
+        let mut decoded: Cow<str> = Cow::proto_default();
+        decoded
+            .deserialize_field(ProtoType::String, WireType::Len, &mut &v[1..])
+            .unwrap();
+        assert_eq!("hi", decoded);
+    }
+}
@@ -1,10 +1,9 @@
-use anyhow::{anyhow, Result};
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
 use std::io;
-use syn::{GenericArgument, Path, Type};
+use syn::{Error, GenericArgument, Path, PathSegment, Result, Type};
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WireType {
     VarInt = 0,
     I64 = 1,
@@ -29,7 +28,7 @@ impl TryFrom<u8> for WireType {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ProtoType {
     Int32,
     Int64,
@@ -52,6 +51,7 @@ pub enum ProtoType {
 }
 
 impl ProtoType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<ProtoType> {
         match s {
             "int32" => Some(Self::Int32),
@@ -73,6 +73,65 @@ impl ProtoType {
             _ => None,
         }
     }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int32 => "int32",
+            Self::Int64 => "int64",
+            Self::UInt32 => "uint32",
+            Self::UInt64 => "uint64",
+            Self::SInt32 => "sint32",
+            Self::SInt64 => "sint64",
+            Self::Bool => "bool",
+            Self::Enum => "enum",
+            Self::Fixed64 => "fixed64",
+            Self::SFixed64 => "sfixed64",
+            Self::Double => "double",
+            Self::String => "string",
+            Self::Bytes => "bytes",
+            Self::Message => "message",
+            Self::Fixed32 => "fixed32",
+            Self::SFixed32 => "sfixed32",
+            Self::Float => "float",
+            Self::Other => "other",
+        }
+    }
+
+    /// How values of this type are laid out on the wire.
+    pub fn wire_type(self) -> WireType {
+        match self {
+            Self::Int32
+            | Self::Int64
+            | Self::UInt32
+            | Self::UInt64
+            | Self::SInt32
+            | Self::SInt64
+            | Self::Bool
+            | Self::Enum => WireType::VarInt,
+            Self::Fixed64 | Self::SFixed64 | Self::Double => WireType::I64,
+            Self::Fixed32 | Self::SFixed32 | Self::Float => WireType::I32,
+            Self::String | Self::Bytes | Self::Message | Self::Other => WireType::Len,
+        }
+    }
+
+    /// The proto types a field may declare with `#[pbtype]`, given the type inferred
+    /// from its Rust type.
+    pub fn compatible_types(self) -> &'static [ProtoType] {
+        match self {
+            Self::Int32 => &[Self::Int32, Self::SInt32, Self::SFixed32, Self::Enum],
+            Self::Int64 => &[Self::Int64, Self::SInt64, Self::SFixed64],
+            Self::UInt32 => &[Self::UInt32, Self::Fixed32],
+            Self::UInt64 => &[Self::UInt64, Self::Fixed64],
+            Self::Bool => &[Self::Bool],
+            Self::Float => &[Self::Float],
+            Self::Double => &[Self::Double],
+            Self::String => &[Self::String],
+            Self::Bytes => &[Self::Bytes],
+            // Types zombie doesn't know encode themselves; the only useful hint is that
+            // they're an enum.
+            _ => &[Self::Enum],
+        }
+    }
 }
 
 impl ToTokens for ProtoType {
@@ -106,10 +165,14 @@ fn get_param_type(ty: &str, path: &Path) -> Option<Type> {
         return None;
     }
     let first = path.segments.first().unwrap();
-    if first.ident.to_string() != ty {
+    if first.ident != ty {
         return None;
     }
-    match &first.arguments {
+    get_single_arg(first)
+}
+
+fn get_single_arg(segment: &PathSegment) -> Option<Type> {
+    match &segment.arguments {
         syn::PathArguments::AngleBracketed(args) => {
             if args.args.len() != 1 {
                 return None;
@@ -131,34 +194,91 @@ fn get_option_type(path: &Path) -> Option<Type> {
     get_param_type("Option", path)
 }
 
+/// Pointers are matched on their last segment, like `Cow`, so `std::rc::Rc<T>` works.
+fn get_pointee_type(path: &Path) -> Option<Type> {
+    let last = path.segments.last()?;
+    if !["Box", "Rc", "Arc"].iter().any(|ptr| last.ident == ptr) {
+        return None;
+    }
+    get_single_arg(last)
+}
+
+/// `Cow<'a, B>` carries a lifetime before the borrowed type, so it is looked up apart
+/// from the single-parameter wrappers.
+fn get_cow_type(path: &Path) -> Option<Type> {
+    let last = path.segments.last()?;
+    if last.ident != "Cow" {
+        return None;
+    }
+    match &last.arguments {
+        syn::PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(arg) => Some(arg.clone()),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+pub fn is_option_type(ty: &Type) -> bool {
+    option_inner_type(ty).is_some()
+}
+
+/// The `T` of an `Option<T>` field type.
+pub fn option_inner_type(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Path(path) => get_option_type(&path.path),
+        Type::Group(group) => option_inner_type(group.elem.as_ref()),
+        Type::Paren(paren) => option_inner_type(paren.elem.as_ref()),
+        _ => None,
+    }
+}
+
+/// The `T` of a `Vec<T>` field type.
+pub fn vec_inner_type(ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Path(path) => get_vec_type(&path.path),
+        Type::Group(group) => vec_inner_type(group.elem.as_ref()),
+        Type::Paren(paren) => vec_inner_type(paren.elem.as_ref()),
+        _ => None,
+    }
+}
+
+fn unsupported(ty: &Type, what: &str) -> Error {
+    Error::new_spanned(ty, format!("unsupported field type: {}", what))
+}
+
 pub fn infer_proto_type(ty: &Type) -> Result<ProtoType> {
     match ty.clone() {
-        Type::Array(_) => Err(anyhow!("unsupported type: array")),
-        Type::BareFn(_) => Err(anyhow!("unsupported type: bare fn")),
-        Type::Group(_) => Err(anyhow!("unsupported type: group")),
-        Type::ImplTrait(_) => Err(anyhow!("unsupported type: impl trait")),
-        Type::Infer(_) => Err(anyhow!("unsupported type: infer")),
-        Type::Macro(_) => Err(anyhow!("unsupported type: macro")),
-        Type::Never(_) => Err(anyhow!("unsupported type: never")),
-        Type::Paren(_) => Err(anyhow!("unsupported type: paren")),
+        Type::Array(array) => match array.elem.as_ref() {
+            Type::Path(path) if path.path.is_ident("u8") => Ok(ProtoType::Bytes),
+            _ => Err(unsupported(ty, "array of non-u8 elements")),
+        },
+        Type::BareFn(_) => Err(unsupported(ty, "bare fn")),
+        Type::Group(group) => infer_proto_type(group.elem.as_ref()),
+        Type::ImplTrait(_) => Err(unsupported(ty, "impl trait")),
+        Type::Infer(_) => Err(unsupported(ty, "infer")),
+        Type::Macro(_) => Err(unsupported(ty, "macro")),
+        Type::Never(_) => Err(unsupported(ty, "never")),
+        Type::Paren(paren) => infer_proto_type(paren.elem.as_ref()),
         Type::Path(path) => {
-            if path.path.is_ident("i32") {
+            if ["i8", "i16", "i32"].iter().any(|ty| path.path.is_ident(ty)) {
                 Ok(ProtoType::Int32)
-            } else if path.path.is_ident("i64") {
+            } else if ["i64", "isize"].iter().any(|ty| path.path.is_ident(ty)) {
                 Ok(ProtoType::Int64)
-            } else if path.path.is_ident("u32") {
+            } else if ["u8", "u16", "u32", "char"]
+                .iter()
+                .any(|ty| path.path.is_ident(ty))
+            {
                 Ok(ProtoType::UInt32)
-            } else if path.path.is_ident("u64") {
+            } else if ["u64", "usize"].iter().any(|ty| path.path.is_ident(ty)) {
                 Ok(ProtoType::UInt64)
             } else if path.path.is_ident("bool") {
                 Ok(ProtoType::Bool)
             } else if path.path.is_ident("f32") {
-                Ok(ProtoType::Fixed32)
+                Ok(ProtoType::Float)
             } else if path.path.is_ident("f64") {
-                Ok(ProtoType::Fixed64)
-            } else if path.path.is_ident("String") {
-                Ok(ProtoType::String)
-            } else if path.path.is_ident("str") {
+                Ok(ProtoType::Double)
+            } else if path.path.is_ident("String") || path.path.is_ident("str") {
                 Ok(ProtoType::String)
             } else if let Some(vec_type) = get_vec_type(&path.path) {
                 if let Type::Path(vec_type_path) = &vec_type {
@@ -172,33 +292,95 @@ pub fn infer_proto_type(ty: &Type) -> Result<ProtoType> {
                 }
             } else if let Some(opt_type) = get_option_type(&path.path) {
                 infer_proto_type(&opt_type)
+            } else if let Some(pointee) = get_pointee_type(&path.path) {
+                infer_proto_type(&pointee)
+            } else if let Some(cow_type) = get_cow_type(&path.path) {
+                infer_proto_type(&cow_type)
             } else {
                 // We have to assume this is some type that can handle itself.
                 Ok(ProtoType::Other)
             }
         }
-        Type::Ptr(_) => Err(anyhow!("unsupported type: ptr")),
+        Type::Ptr(_) => Err(unsupported(ty, "ptr")),
         Type::Reference(r) => infer_proto_type(r.elem.as_ref()),
         Type::Slice(s) => {
             if let Type::Path(path) = s.elem.as_ref() {
                 if path.path.is_ident("u8") {
                     Ok(ProtoType::Bytes)
                 } else {
-                    Err(anyhow!(
-                        "unsupported slice type: {:?}",
-                        path.path.to_token_stream()
-                    ))
+                    Err(unsupported(ty, "slice of non-u8 elements"))
                 }
             } else {
-                Err(anyhow!("unsupported type: slice"))
+                Err(unsupported(ty, "slice"))
             }
         }
-        Type::TraitObject(_) => Err(anyhow!("unsupported type: trait object")),
-        Type::Tuple(_) => Err(anyhow!("unsupported type: tuple")),
-        Type::Verbatim(_) => Err(anyhow!("unsupported type: verbatim")),
-        _ => Err(anyhow!(
-            "unsupported type: {}",
-            ty.to_token_stream().to_string()
-        )),
+        Type::TraitObject(_) => Err(unsupported(ty, "trait object")),
+        Type::Tuple(_) => Err(unsupported(ty, "tuple")),
+        Type::Verbatim(_) => Err(unsupported(ty, "verbatim")),
+        _ => Err(unsupported(ty, &ty.to_token_stream().to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(ty: &str) -> ProtoType {
+        infer_proto_type(&syn::parse_str(ty).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn infer_primitives() {
+        assert_eq!(ProtoType::Int32, infer("i32"));
+        assert_eq!(ProtoType::Int64, infer("i64"));
+        assert_eq!(ProtoType::UInt32, infer("u32"));
+        assert_eq!(ProtoType::UInt64, infer("u64"));
+        assert_eq!(ProtoType::Bool, infer("bool"));
+        assert_eq!(ProtoType::Float, infer("f32"));
+        assert_eq!(ProtoType::Double, infer("f64"));
+        assert_eq!(ProtoType::String, infer("String"));
+        assert_eq!(ProtoType::String, infer("&str"));
+        assert_eq!(ProtoType::Bytes, infer("Vec<u8>"));
+        assert_eq!(ProtoType::Bytes, infer("&[u8]"));
+        assert_eq!(ProtoType::Int64, infer("Option<Vec<i64>>"));
+        assert_eq!(ProtoType::Other, infer("SubMessage"));
+    }
+
+    #[test]
+    fn infer_widened_integers() {
+        assert_eq!(ProtoType::Int32, infer("i8"));
+        assert_eq!(ProtoType::Int32, infer("i16"));
+        assert_eq!(ProtoType::Int64, infer("isize"));
+        assert_eq!(ProtoType::UInt32, infer("u8"));
+        assert_eq!(ProtoType::UInt32, infer("u16"));
+        assert_eq!(ProtoType::UInt32, infer("char"));
+        assert_eq!(ProtoType::UInt64, infer("usize"));
+        assert_eq!(ProtoType::UInt32, infer("Option<u8>"));
+        assert_eq!(ProtoType::Bytes, infer("[u8; 16]"));
+        assert_eq!(ProtoType::Bytes, infer("Vec<[u8; 4]>"));
+    }
+
+    #[test]
+    fn infer_through_pointers() {
+        assert_eq!(ProtoType::Other, infer("Option<Box<Node>>"));
+        assert_eq!(ProtoType::UInt64, infer("Rc<u64>"));
+        assert_eq!(ProtoType::String, infer("Arc<String>"));
+        assert_eq!(ProtoType::UInt64, infer("std::sync::Arc<u64>"));
+        assert_eq!(ProtoType::UInt64, infer("std::rc::Rc<u64>"));
+        assert_eq!(ProtoType::String, infer("Cow<'a, str>"));
+        assert_eq!(ProtoType::Bytes, infer("std::borrow::Cow<'static, [u8]>"));
+    }
+
+    #[test]
+    fn compatible_types_works() {
+        assert!(ProtoType::Int32
+            .compatible_types()
+            .contains(&ProtoType::SFixed32));
+        assert!(!ProtoType::Int32
+            .compatible_types()
+            .contains(&ProtoType::Fixed64));
+        assert!(!ProtoType::String
+            .compatible_types()
+            .contains(&ProtoType::SInt32));
     }
 }
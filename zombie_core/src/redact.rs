@@ -0,0 +1,105 @@
+use crate::descriptor::{bounded_generics, FieldDesc};
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use std::cell::Cell;
+use std::fmt;
+use syn::Generics;
+
+thread_local! {
+    static UNREDACTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// What `#[sensitive]` fields show instead of their value.
+pub const REDACTED: &str = "<redacted>";
+
+/// Whether `#[sensitive]` fields are currently hidden. They are unless inside
+/// [`unredacted`].
+pub fn redacting() -> bool {
+    !UNREDACTED.with(Cell::get)
+}
+
+/// Runs `f` with `#[sensitive]` fields shown in full on this thread, for dumps in tests
+/// and debugging sessions that need the real values.
+pub fn unredacted<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            UNREDACTED.with(|unredacted| unredacted.set(self.0));
+        }
+    }
+
+    let _restore = Restore(UNREDACTED.with(|unredacted| unredacted.replace(true)));
+    f()
+}
+
+/// Stands in for a `#[sensitive]` field in generated `Debug` output.
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// A `Debug` impl for `#[zombie(debug)]` messages that hides `#[sensitive]` fields.
+/// Skipped fields aren't shown, since they needn't implement `Debug`.
+pub(crate) fn derive_debug_struct(
+    name: &Ident,
+    generics: &Generics,
+    fields: &[FieldDesc],
+    has_skipped: bool,
+) -> TokenStream {
+    let generics = bounded_generics(generics, quote! { ::core::fmt::Debug });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let message = name.to_string();
+    let entries = fields.iter().map(|field| {
+        let ident = &field.name;
+        let label = ident.to_string();
+        if field.sensitive {
+            quote! {
+                if zombie::redacting() {
+                    s.field(#label, &zombie::Redacted);
+                } else {
+                    s.field(#label, &self.#ident);
+                }
+            }
+        } else {
+            quote! {
+                s.field(#label, &self.#ident);
+            }
+        }
+    });
+    let finish = if has_skipped {
+        quote! { s.finish_non_exhaustive() }
+    } else {
+        quote! { s.finish() }
+    };
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics ::core::fmt::Debug for #name #ty_generics #where_clause {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                let mut s = f.debug_struct(#message);
+                #(#entries)*
+                #finish
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unredacted_restores_on_exit() {
+        assert!(redacting());
+        unredacted(|| {
+            assert!(!redacting());
+            unredacted(|| assert!(!redacting()));
+            assert!(!redacting());
+        });
+        assert!(redacting());
+    }
+}
@@ -1,17 +1,62 @@
 use crate::{
-    descriptor::{extract_fields, FieldDesc},
+    descriptor::{
+        bounded_generics, extract_fields, extract_message_options, extract_skipped_fields,
+        FieldDesc, MessageOptions, Syntax,
+    },
+    name::full_name,
     proto_type::{ProtoType, WireType},
+    redact::derive_debug_struct,
+    wire::Descriptor,
 };
 
-use anyhow::Result;
 use proc_macro2::{Ident, TokenStream};
 use quote::quote;
 use std::io::{self, ErrorKind, Write};
-use syn::{Data, DataEnum, DataStruct, DeriveInput};
+use syn::{Data, DataStruct, DeriveInput, Error, Generics, Result};
 
+/// Output is deterministic: derived messages write their fields in field-number
+/// order whatever order they're declared in, so equal messages encode to the same
+/// bytes.
 pub trait Serialize {
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()>;
     fn serialize(&self, w: &mut impl Write) -> io::Result<()>;
+
+    /// Whether this is the zero value that proto3 implicit-presence fields leave off the wire.
+    fn is_default(&self) -> bool {
+        false
+    }
+
+    /// Describes the message this type encodes as, for reading its bytes without the
+    /// type. `None` for scalars; collections and pointers describe what they hold.
+    fn descriptor() -> Option<Descriptor> {
+        None
+    }
+
+    /// Writes `items` as a repeated field. `u8` overrides this so that `Vec<u8>` is `bytes`.
+    fn serialize_repeated_field(
+        items: &[Self],
+        id: u64,
+        pbtype: ProtoType,
+        w: &mut impl Write,
+    ) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        for item in items.iter() {
+            item.serialize_field(id, pbtype, w)?;
+        }
+        Ok(())
+    }
+
+    fn serialize_repeated(items: &[Self], w: &mut impl Write) -> io::Result<()>
+    where
+        Self: Sized,
+    {
+        for item in items.iter() {
+            item.serialize(w)?;
+        }
+        Ok(())
+    }
 }
 
 pub fn write_tag(w: &mut impl Write, wiretype: WireType, id: u64) -> io::Result<()> {
@@ -20,15 +65,59 @@ pub fn write_tag(w: &mut impl Write, wiretype: WireType, id: u64) -> io::Result<
     write_uvarint(w, tag)
 }
 
+/// Whether `encoded`, a field value as written by a codec, is the zero value of
+/// `pbtype`, which proto3 leaves off the wire: no bytes for a length-delimited type,
+/// and otherwise a varint or fixed-width zero.
+pub fn is_default_encoding(pbtype: ProtoType, encoded: &[u8]) -> bool {
+    match pbtype.wire_type() {
+        WireType::Len => encoded.is_empty(),
+        WireType::VarInt => encoded == [0],
+        WireType::I32 | WireType::I64 => encoded.iter().all(|byte| *byte == 0),
+    }
+}
+
+/// Writes the tag of a field whose value is `len` bytes long, followed by the length
+/// if `pbtype` is length-delimited. Fixed-width types must have exactly their width.
+pub fn write_field_header(
+    w: &mut impl Write,
+    id: u64,
+    pbtype: ProtoType,
+    len: usize,
+) -> io::Result<()> {
+    let wiretype = pbtype.wire_type();
+    let width = match wiretype {
+        WireType::I32 => Some(4),
+        WireType::I64 => Some(8),
+        _ => None,
+    };
+    if let Some(width) = width.filter(|width| *width != len) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} value for field {} is {} bytes, expected {}",
+                pbtype.name(),
+                id,
+                len,
+                width
+            ),
+        ));
+    }
+    write_tag(w, wiretype, id)?;
+    if wiretype == WireType::Len {
+        write_uvarint(w, len as u64)?;
+    }
+    Ok(())
+}
+
 pub fn write_uvarint(w: &mut impl Write, n: u64) -> io::Result<()> {
     let mut buf: [u8; 10] = [0; 10];
     let mut i = 0usize;
     let mut n = n;
     loop {
         let mut a = (n & 0b01111111) as u8;
-        n = n >> 7;
+        n >>= 7;
         if n != 0 {
-            a = a | 0b10000000;
+            a |= 0b10000000;
         }
         buf[i] = a;
         i += 1;
@@ -47,15 +136,25 @@ fn encode_zigzag(n: i64) -> u64 {
     let neg = n < 0;
     let mut n = n << 1;
     if neg {
-        n = n ^ !0;
+        n ^= !0;
     }
     u64::from_le_bytes(n.to_le_bytes())
 }
 
+/// The derive rejects incompatible `#[pbtype]`s, so this is only reachable by calling
+/// `serialize_field` by hand.
+fn invalid_pbtype(ty: &str, pbtype: ProtoType) -> io::Error {
+    io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("invalid pbtype for {}: {:?}", ty, pbtype),
+    )
+}
+
 impl Serialize for i32 {
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         match pbtype {
-            ProtoType::Int32 => {
+            // Generic fields are `Other`, since the derive can't see their type.
+            ProtoType::Int32 | ProtoType::Enum | ProtoType::Other => {
                 write_tag(w, WireType::VarInt, id)?;
                 write_ivarint(w, i64::from(*self))
             }
@@ -67,49 +166,51 @@ impl Serialize for i32 {
                 write_tag(w, WireType::I32, id)?;
                 w.write_all(&self.to_le_bytes())
             }
-            _ => Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!("invalid pbtype for i32: {:?}", pbtype),
-            )),
+            _ => Err(invalid_pbtype("i32", pbtype)),
         }
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         write_ivarint(w, i64::from(*self))
     }
+
+    fn is_default(&self) -> bool {
+        *self == 0
+    }
 }
 
 impl Serialize for i64 {
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         match pbtype {
-            ProtoType::Int64 => {
+            ProtoType::Int64 | ProtoType::Other => {
                 write_tag(w, WireType::VarInt, id)?;
-                write_ivarint(w, i64::from(*self))
+                write_ivarint(w, *self)
             }
             ProtoType::SInt64 => {
                 write_tag(w, WireType::VarInt, id)?;
-                write_uvarint(w, encode_zigzag(i64::from(*self)))
+                write_uvarint(w, encode_zigzag(*self))
             }
             ProtoType::SFixed64 => {
                 write_tag(w, WireType::I64, id)?;
                 w.write_all(&self.to_le_bytes())
             }
-            _ => Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!("invalid pbtype for i64: {:?}", pbtype),
-            )),
+            _ => Err(invalid_pbtype("i64", pbtype)),
         }
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
-        write_ivarint(w, i64::from(*self))
+        write_ivarint(w, *self)
+    }
+
+    fn is_default(&self) -> bool {
+        *self == 0
     }
 }
 
 impl Serialize for u32 {
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         match pbtype {
-            ProtoType::UInt32 => {
+            ProtoType::UInt32 | ProtoType::Other => {
                 write_tag(w, WireType::VarInt, id)?;
                 write_uvarint(w, u64::from(*self))
             }
@@ -117,38 +218,103 @@ impl Serialize for u32 {
                 write_tag(w, WireType::I32, id)?;
                 w.write_all(&self.to_le_bytes())
             }
-            _ => Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!("invalid pbtype for u32: {:?}", pbtype),
-            )),
+            _ => Err(invalid_pbtype("u32", pbtype)),
         }
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         write_uvarint(w, u64::from(*self))
     }
+
+    fn is_default(&self) -> bool {
+        *self == 0
+    }
 }
 
 impl Serialize for u64 {
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         match pbtype {
-            ProtoType::UInt64 => {
+            ProtoType::UInt64 | ProtoType::Other => {
                 write_tag(w, WireType::VarInt, id)?;
-                write_uvarint(w, u64::from(*self))
+                write_uvarint(w, *self)
             }
             ProtoType::Fixed64 => {
                 write_tag(w, WireType::I64, id)?;
                 w.write_all(&self.to_le_bytes())
             }
-            _ => Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!("invalid pbtype for u64: {:?}", pbtype),
-            )),
+            _ => Err(invalid_pbtype("u64", pbtype)),
         }
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
-        write_uvarint(w, u64::from(*self))
+        write_uvarint(w, *self)
+    }
+
+    fn is_default(&self) -> bool {
+        *self == 0
+    }
+}
+
+// Integer types without a protobuf scalar of their own are widened to the one that
+// holds them.
+macro_rules! impl_serialize_widened {
+    ($($ty:ty => $wide:ty),* $(,)?) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize_field(
+                    &self,
+                    id: u64,
+                    pbtype: ProtoType,
+                    w: &mut impl Write,
+                ) -> io::Result<()> {
+                    (*self as $wide).serialize_field(id, pbtype, w)
+                }
+
+                fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+                    (*self as $wide).serialize(w)
+                }
+
+                fn is_default(&self) -> bool {
+                    (*self as $wide).is_default()
+                }
+            }
+        )*
+    };
+}
+
+impl_serialize_widened! {
+    i8 => i32,
+    i16 => i32,
+    isize => i64,
+    u16 => u32,
+    usize => u64,
+    char => u32,
+}
+
+impl Serialize for u8 {
+    fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        u32::from(*self).serialize_field(id, pbtype, w)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        u32::from(*self).serialize(w)
+    }
+
+    fn is_default(&self) -> bool {
+        *self == 0
+    }
+
+    fn serialize_repeated_field(
+        items: &[Self],
+        id: u64,
+        pbtype: ProtoType,
+        w: &mut impl Write,
+    ) -> io::Result<()> {
+        items.serialize_field(id, pbtype, w)
+    }
+
+    fn serialize_repeated(items: &[Self], w: &mut impl Write) -> io::Result<()> {
+        items.serialize(w)
     }
 }
 
@@ -161,6 +327,10 @@ impl Serialize for bool {
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         write_uvarint(w, if *self { 1 } else { 0 })
     }
+
+    fn is_default(&self) -> bool {
+        !*self
+    }
 }
 
 impl Serialize for f64 {
@@ -172,6 +342,10 @@ impl Serialize for f64 {
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         w.write_all(&self.to_le_bytes())
     }
+
+    fn is_default(&self) -> bool {
+        self.to_bits() == 0
+    }
 }
 
 impl Serialize for f32 {
@@ -183,6 +357,10 @@ impl Serialize for f32 {
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         w.write_all(&self.to_le_bytes())
     }
+
+    fn is_default(&self) -> bool {
+        self.to_bits() == 0
+    }
 }
 
 impl Serialize for String {
@@ -195,6 +373,10 @@ impl Serialize for String {
         write_uvarint(w, self.len() as u64)?;
         w.write_all(self.as_bytes())
     }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
 }
 
 impl Serialize for str {
@@ -207,9 +389,13 @@ impl Serialize for str {
         write_uvarint(w, self.len() as u64)?;
         w.write_all(self.as_bytes())
     }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
 }
 
-impl Serialize for Vec<u8> {
+impl Serialize for [u8] {
     fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         write_tag(w, WireType::Len, id)?;
         self.serialize(w)
@@ -217,39 +403,69 @@ impl Serialize for Vec<u8> {
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
         write_uvarint(w, self.len() as u64)?;
-        w.write_all(&self[..])
+        w.write_all(self)
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
     }
 }
 
-impl Serialize for &[u8] {
-    fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
-        write_tag(w, WireType::Len, id)?;
-        self.serialize(w)
+impl<const N: usize> Serialize for [u8; N] {
+    fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        self[..].serialize_field(id, pbtype, w)
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
-        write_uvarint(w, self.len() as u64)?;
-        w.write_all(&self)
+        self[..].serialize(w)
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for &T {
+    fn descriptor() -> Option<Descriptor> {
+        T::descriptor()
+    }
+
+    fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        (**self).serialize_field(id, pbtype, w)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        (**self).serialize(w)
+    }
+
+    fn is_default(&self) -> bool {
+        (**self).is_default()
     }
 }
 
 impl<T: Serialize> Serialize for Vec<T> {
+    fn descriptor() -> Option<Descriptor> {
+        T::descriptor()
+    }
+
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
-        for item in self.iter() {
-            item.serialize_field(id, pbtype, w)?;
-        }
-        Ok(())
+        T::serialize_repeated_field(self, id, pbtype, w)
     }
 
     fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
-        for item in self.iter() {
-            item.serialize(w)?;
-        }
-        Ok(())
+        T::serialize_repeated(self, w)
+    }
+
+    fn is_default(&self) -> bool {
+        self.is_empty()
     }
 }
 
 impl<T: Serialize> Serialize for Option<T> {
+    fn descriptor() -> Option<Descriptor> {
+        T::descriptor()
+    }
+
     fn serialize_field(&self, id: u64, pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
         match &self {
             Some(val) => val.serialize_field(id, pbtype, w),
@@ -263,30 +479,166 @@ impl<T: Serialize> Serialize for Option<T> {
             None => Ok(()),
         }
     }
+
+    fn is_default(&self) -> bool {
+        self.is_none()
+    }
 }
 
 impl FieldDesc {
-    fn serialize_value_call(&self) -> TokenStream {
+    fn serialize_value_call(&self, options: &MessageOptions) -> TokenStream {
         let id = self.id;
         let ident = &self.name;
         let ty = self.ty;
+        if let Some(with) = &self.with {
+            let write = if options.syntax == Syntax::Proto3 && !self.optional {
+                // The field type needn't have a notion of default, so check the encoding.
+                quote! {
+                    let mut v = Vec::with_capacity(#with::encoded_len(value));
+                    #with::encode(value, &mut v)?;
+                    if !zombie::is_default_encoding(#ty, &v) {
+                        zombie::write_field_header(w, #id, #ty, v.len())?;
+                        w.write_all(&v)?;
+                    }
+                }
+            } else {
+                quote! {
+                    zombie::write_field_header(w, #id, #ty, #with::encoded_len(value))?;
+                    #with::encode(value, w)?;
+                }
+            };
+            return if self.optional {
+                quote! {
+                    if let Some(value) = &self.#ident {
+                        #write
+                    }
+                }
+            } else {
+                quote! {
+                    {
+                        let value = &self.#ident;
+                        #write
+                    }
+                }
+            };
+        }
+        if options.syntax == Syntax::Proto3 && !self.optional {
+            quote! {
+                if !zombie::Serialize::is_default(&self.#ident) {
+                    self.#ident.serialize_field(#id, #ty, w)?
+                }
+            }
+        } else {
+            quote! {
+                self.#ident.serialize_field(#id, #ty, w)?
+            }
+        }
+    }
+
+    fn field_descriptor(&self) -> TokenStream {
+        let id = self.id;
+        let label = self.name.to_string();
+        let ty = self.ty;
+        let rust_ty = &self.rust_ty;
+        let sensitive = self.sensitive;
+        // Codec fields needn't implement `Serialize`, and are opaque anyway.
+        let message = match self.with {
+            Some(_) => quote! { || None },
+            None => quote! { <#rust_ty as zombie::Serialize>::descriptor },
+        };
         quote! {
-            self.#ident.serialize_field(#id, #ty, w)?
+            zombie::wire::FieldDescriptor {
+                number: #id,
+                name: #label.to_owned(),
+                ty: #ty,
+                message: #message,
+                sensitive: #sensitive,
+            }
+        }
+    }
+
+    fn serialize_required_check(&self) -> TokenStream {
+        let ident = &self.name;
+        let name = ident.to_string();
+        quote! {
+            if self.#ident.is_none() {
+                missing.push(#name);
+            }
         }
     }
 }
 
-fn derive_serialize_struct(name: Ident, data: DataStruct) -> Result<TokenStream> {
-    let fields = extract_fields(data)?;
+fn derive_serialize_struct(
+    name: Ident,
+    generics: Generics,
+    options: MessageOptions,
+    data: DataStruct,
+) -> Result<TokenStream> {
+    let fields = extract_fields(&data, &options)?;
+    if !options.debug {
+        // A plain `#[derive(Debug)]` would print sensitive fields as they are.
+        let sensitive = data
+            .fields
+            .iter()
+            .flat_map(|field| field.attrs.iter())
+            .find(|attr| attr.path.is_ident("sensitive"));
+        if let Some(attr) = sensitive {
+            return Err(Error::new_spanned(
+                attr,
+                "`#[sensitive]` needs `#[zombie(debug)]` on the message, in place of `#[derive(Debug)]`",
+            ));
+        }
+    }
+
+    let debug_impl = if options.debug {
+        let has_skipped = !extract_skipped_fields(&data)?.is_empty();
+        derive_debug_struct(&name, &generics, &fields, has_skipped)
+    } else {
+        quote! {}
+    };
+
+    let generics = bounded_generics(&generics, quote! { zombie::Serialize });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
-    let fields = fields
+    let required = fields
+        .iter()
+        .filter(|field| field.required && field.optional)
+        .map(|field| field.serialize_required_check())
+        .collect::<Vec<TokenStream>>();
+    let required = if required.is_empty() {
+        quote! {}
+    } else {
+        let message = name.to_string();
+        quote! {
+            let mut missing: Vec<&str> = Vec::new();
+            #(#required)*
+            if !missing.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("missing required fields in {}: {}", #message, missing.join(", ")),
+                ));
+            }
+        }
+    };
+
+    let full_name = full_name(&name, &options);
+    let descriptors = fields
+        .iter()
+        .map(|field| field.field_descriptor())
+        .collect::<Vec<TokenStream>>();
+
+    // Fields are written in field-number order whatever order they're declared in,
+    // as protoc-generated code does.
+    let mut sorted = fields.iter().collect::<Vec<&FieldDesc>>();
+    sorted.sort_by_key(|field| field.id);
+    let fields = sorted
         .into_iter()
-        .map(|field| field.serialize_value_call())
+        .map(|field| field.serialize_value_call(&options))
         .collect::<Vec<TokenStream>>();
 
     let out: TokenStream = quote! {
         #[automatically_derived]
-        impl zombie::Serialize for #name {
+        impl #impl_generics zombie::Serialize for #name #ty_generics #where_clause {
             fn serialize_field(&self, id: u64, pbtype: zombie::ProtoType, w: &mut impl std::io::Write) -> std::io::Result<()> {
                 zombie::write_tag(w, zombie::WireType::Len, id)?;
                 let mut v = Vec::new();
@@ -295,18 +647,27 @@ fn derive_serialize_struct(name: Ident, data: DataStruct) -> Result<TokenStream>
                 w.write_all(&v[..])
             }
 
+            fn descriptor() -> Option<zombie::wire::Descriptor> {
+                Some(zombie::wire::Descriptor {
+                    name: #full_name.to_owned(),
+                    fields: vec![#(#descriptors),*],
+                })
+            }
+
             fn serialize(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+                #required
                 #(#fields);*;
                 std::io::Result::Ok(())
             }
         }
-    }
-    .into();
+
+        #debug_impl
+    };
 
     Ok(out)
 }
 
-fn derive_serialize_enum(name: Ident, _data: DataEnum) -> Result<TokenStream> {
+fn derive_serialize_enum(name: Ident) -> Result<TokenStream> {
     let out: TokenStream = quote! {
         #[automatically_derived]
         impl zombie::Serialize for #name {
@@ -319,18 +680,27 @@ fn derive_serialize_enum(name: Ident, _data: DataEnum) -> Result<TokenStream> {
                 zombie::write_uvarint(w, self.clone() as u64)?;
                 std::io::Result::Ok(())
             }
+
+            fn is_default(&self) -> bool {
+                self.clone() as u64 == 0
+            }
         }
-    }
-    .into();
+    };
 
     Ok(out)
 }
 
 pub fn derive_serialize(input: DeriveInput) -> Result<TokenStream> {
     match input.data {
-        Data::Struct(data) => derive_serialize_struct(input.ident, data),
-        Data::Enum(data) => derive_serialize_enum(input.ident, data),
-        _ => panic!("![derive(Serialize)] only works on structs and enums"),
+        Data::Struct(data) => {
+            let options = extract_message_options(&input.attrs)?;
+            derive_serialize_struct(input.ident, input.generics, options, data)
+        }
+        Data::Enum(_) => derive_serialize_enum(input.ident),
+        Data::Union(data) => Err(Error::new(
+            data.union_token.span,
+            "#[derive(Serialize)] only works on structs and enums",
+        )),
     }
 }
 
@@ -376,6 +746,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn integer_serialize_rejects_invalid_pbtype() {
+        let mut buf: Vec<u8> = Vec::new();
+        let error = 1i32
+            .serialize_field(1, ProtoType::Fixed64, &mut buf)
+            .unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+        assert!(1u64
+            .serialize_field(1, ProtoType::SInt64, &mut buf)
+            .is_err());
+        assert!(buf.is_empty());
+        1i32.serialize_field(1, ProtoType::Enum, &mut buf).unwrap();
+        assert_eq!(buf, vec![0x08, 0x01]);
+    }
+
     #[test]
     fn encode_sint_works() {
         assert_eq!(0, encode_zigzag(0));
@@ -0,0 +1,392 @@
+use crate::{
+    deserialize::{
+        read_len, read_tag, skip_field, DeserializeError, DeserializeField, ProtoDefault,
+    },
+    merge::MergeField,
+    proto_type::{ProtoType, WireType},
+    serialize::{write_tag, write_uvarint, Serialize},
+    wire::{Descriptor, FieldDescriptor},
+};
+
+use std::{
+    io::{self, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const NANOS_PER_SECOND: i32 = 1_000_000_000;
+
+/// The seconds a `google.protobuf.Timestamp` may hold: 0001-01-01 to 9999-12-31.
+pub const MIN_TIMESTAMP_SECONDS: i64 = -62_135_596_800;
+pub const MAX_TIMESTAMP_SECONDS: i64 = 253_402_300_799;
+
+/// The largest `google.protobuf.Duration`, about 10,000 years.
+pub const MAX_DURATION_SECONDS: i64 = 315_576_000_000;
+
+/// Splits a time into the `seconds` and `nanos` of a `google.protobuf.Timestamp`.
+/// Times more than `i64::MAX` seconds from the epoch saturate.
+pub fn system_time_to_parts(time: &SystemTime) -> (i64, i32) {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => {
+            let seconds = i64::try_from(after.as_secs()).unwrap_or(i64::MAX);
+            (seconds, after.subsec_nanos() as i32)
+        }
+        Err(err) => {
+            // Timestamps before the epoch still count nanos forward.
+            let before = err.duration();
+            let seconds = i64::try_from(before.as_secs())
+                .ok()
+                .and_then(i64::checked_neg)
+                .unwrap_or(i64::MIN);
+            match before.subsec_nanos() as i32 {
+                0 => (seconds, 0),
+                nanos => match seconds.checked_sub(1) {
+                    Some(seconds) => (seconds, NANOS_PER_SECOND - nanos),
+                    None => (i64::MIN, 0),
+                },
+            }
+        }
+    }
+}
+
+fn check_range(ty: &str, value: i64, min: i64, max: i64) -> Result<(), DeserializeError> {
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(DeserializeError::OutOfRange {
+            ty: ty.to_owned(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Joins the `seconds` and `nanos` of a `google.protobuf.Timestamp`, which must be
+/// within the Timestamp range and representable as a `SystemTime` on this platform.
+pub fn system_time_from_parts(seconds: i64, nanos: i32) -> Result<SystemTime, DeserializeError> {
+    check_range(
+        "google.protobuf.Timestamp.seconds",
+        seconds,
+        MIN_TIMESTAMP_SECONDS,
+        MAX_TIMESTAMP_SECONDS,
+    )?;
+    check_range(
+        "google.protobuf.Timestamp.nanos",
+        nanos as i64,
+        0,
+        NANOS_PER_SECOND as i64 - 1,
+    )?;
+    let time = if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs()))
+    };
+    time.and_then(|time| time.checked_add(Duration::from_nanos(nanos as u64)))
+        .ok_or_else(|| DeserializeError::OutOfRange {
+            ty: "SystemTime".to_owned(),
+            value: format!("{}s {}ns", seconds, nanos),
+        })
+}
+
+/// The encoding of times outside the Timestamp range wouldn't decode.
+fn check_timestamp_seconds(seconds: i64) -> io::Result<()> {
+    if (MIN_TIMESTAMP_SECONDS..=MAX_TIMESTAMP_SECONDS).contains(&seconds) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "time {}s is out of range for google.protobuf.Timestamp",
+                seconds
+            ),
+        ))
+    }
+}
+
+fn duration_to_parts(duration: &Duration) -> io::Result<(i64, i32)> {
+    match i64::try_from(duration.as_secs()) {
+        Ok(seconds) if seconds <= MAX_DURATION_SECONDS => {
+            Ok((seconds, duration.subsec_nanos() as i32))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "duration {}s is out of range for google.protobuf.Duration",
+                duration.as_secs()
+            ),
+        )),
+    }
+}
+
+fn write_seconds_nanos(w: &mut impl Write, seconds: i64, nanos: i32) -> io::Result<()> {
+    let mut v = Vec::new();
+    if seconds != 0 {
+        seconds.serialize_field(1, ProtoType::Int64, &mut v)?;
+    }
+    if nanos != 0 {
+        nanos.serialize_field(2, ProtoType::Int32, &mut v)?;
+    }
+    write_uvarint(w, v.len() as u64)?;
+    w.write_all(&v[..])
+}
+
+/// Describes a `google.protobuf.Timestamp` or `google.protobuf.Duration`.
+fn seconds_nanos_descriptor(name: &str) -> Descriptor {
+    let field = |number, name: &str, ty| FieldDescriptor {
+        number,
+        name: name.to_owned(),
+        ty,
+        message: || None,
+        sensitive: false,
+    };
+    Descriptor {
+        name: name.to_owned(),
+        fields: vec![
+            field(1, "seconds", ProtoType::Int64),
+            field(2, "nanos", ProtoType::Int32),
+        ],
+    }
+}
+
+fn read_seconds_nanos(
+    r: &mut impl Read,
+    seconds: &mut i64,
+    nanos: &mut i32,
+) -> Result<(), DeserializeError> {
+    let v = read_len(r)?;
+    let r = &mut &v[..];
+    while let Some((id, wiretype)) = read_tag(r)? {
+        match id {
+            1 => seconds.deserialize_field(ProtoType::Int64, wiretype, r)?,
+            2 => nanos.deserialize_field(ProtoType::Int32, wiretype, r)?,
+            _ => skip_field(wiretype, r)?,
+        }
+    }
+    Ok(())
+}
+
+impl Serialize for SystemTime {
+    fn descriptor() -> Option<Descriptor> {
+        Some(seconds_nanos_descriptor("google.protobuf.Timestamp"))
+    }
+
+    fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        let (seconds, nanos) = system_time_to_parts(self);
+        check_timestamp_seconds(seconds)?;
+        write_tag(w, WireType::Len, id)?;
+        write_seconds_nanos(w, seconds, nanos)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        let (seconds, nanos) = system_time_to_parts(self);
+        check_timestamp_seconds(seconds)?;
+        write_seconds_nanos(w, seconds, nanos)
+    }
+}
+
+impl DeserializeField for SystemTime {
+    fn deserialize_field(
+        &mut self,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        if let WireType::Len = wiretype {
+            let (mut seconds, mut nanos) = system_time_to_parts(self);
+            read_seconds_nanos(r, &mut seconds, &mut nanos)?;
+            *self = system_time_from_parts(seconds, nanos)?;
+            Ok(())
+        } else {
+            Err(DeserializeError::TypeError(format!(
+                "invalid wiretype for SystemTime: {:?}",
+                wiretype
+            )))
+        }
+    }
+}
+
+impl ProtoDefault for SystemTime {
+    fn proto_default() -> Self {
+        UNIX_EPOCH
+    }
+}
+
+impl MergeField for SystemTime {
+    fn merge_field(&mut self, other: &Self) {
+        *self = *other;
+    }
+
+    fn clear_field(&mut self) {
+        *self = UNIX_EPOCH;
+    }
+}
+
+impl Serialize for Duration {
+    fn descriptor() -> Option<Descriptor> {
+        Some(seconds_nanos_descriptor("google.protobuf.Duration"))
+    }
+
+    fn serialize_field(&self, id: u64, _pbtype: ProtoType, w: &mut impl Write) -> io::Result<()> {
+        let (seconds, nanos) = duration_to_parts(self)?;
+        write_tag(w, WireType::Len, id)?;
+        write_seconds_nanos(w, seconds, nanos)
+    }
+
+    fn serialize(&self, w: &mut impl Write) -> io::Result<()> {
+        let (seconds, nanos) = duration_to_parts(self)?;
+        write_seconds_nanos(w, seconds, nanos)
+    }
+}
+
+impl DeserializeField for Duration {
+    fn deserialize_field(
+        &mut self,
+        _prototype: ProtoType,
+        wiretype: WireType,
+        r: &mut impl Read,
+    ) -> Result<(), DeserializeError> {
+        if let WireType::Len = wiretype {
+            let mut seconds = i64::try_from(self.as_secs()).unwrap_or(i64::MAX);
+            let mut nanos = self.subsec_nanos() as i32;
+            read_seconds_nanos(r, &mut seconds, &mut nanos)?;
+            if seconds < 0 || nanos < 0 {
+                return Err(DeserializeError::TypeError(format!(
+                    "negative duration: {}s {}ns",
+                    seconds, nanos
+                )));
+            }
+            check_range(
+                "google.protobuf.Duration.seconds",
+                seconds,
+                0,
+                MAX_DURATION_SECONDS,
+            )?;
+            check_range(
+                "google.protobuf.Duration.nanos",
+                nanos as i64,
+                0,
+                NANOS_PER_SECOND as i64 - 1,
+            )?;
+            *self = Duration::new(seconds as u64, nanos as u32);
+            Ok(())
+        } else {
+            Err(DeserializeError::TypeError(format!(
+                "invalid wiretype for Duration: {:?}",
+                wiretype
+            )))
+        }
+    }
+}
+
+impl ProtoDefault for Duration {
+    fn proto_default() -> Self {
+        Duration::ZERO
+    }
+}
+
+impl MergeField for Duration {
+    fn merge_field(&mut self, other: &Self) {
+        *self = *other;
+    }
+
+    fn clear_field(&mut self) {
+        *self = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_time_parts_after_epoch() {
+        let time = UNIX_EPOCH + Duration::new(5, 6);
+        assert_eq!((5, 6), system_time_to_parts(&time));
+        assert_eq!(time, system_time_from_parts(5, 6).unwrap());
+    }
+
+    #[test]
+    fn system_time_parts_before_epoch() {
+        let time = UNIX_EPOCH - Duration::new(1, 500_000_000);
+        assert_eq!((-2, 500_000_000), system_time_to_parts(&time));
+        assert_eq!(time, system_time_from_parts(-2, 500_000_000).unwrap());
+    }
+
+    #[test]
+    fn system_time_parts_out_of_range() {
+        assert!(system_time_from_parts(i64::MIN, 0).is_err());
+        assert!(system_time_from_parts(MAX_TIMESTAMP_SECONDS + 1, 0).is_err());
+        assert!(system_time_from_parts(0, -1).is_err());
+        assert!(system_time_from_parts(0, NANOS_PER_SECOND).is_err());
+        assert!(system_time_from_parts(MIN_TIMESTAMP_SECONDS, 0).is_ok());
+    }
+
+    #[test]
+    fn system_time_rejects_out_of_range_fields() {
+        // Timestamp { seconds: i64::MIN }, twice: the second read used to start from
+        // the first and overflow while splitting it.
+        let mut timestamp = vec![0x0b, 0x08];
+        timestamp.extend([0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01]);
+        for _ in 0..2 {
+            let mut time = UNIX_EPOCH;
+            let result =
+                time.deserialize_field(ProtoType::Message, WireType::Len, &mut &timestamp[..]);
+            assert!(matches!(result, Err(DeserializeError::OutOfRange { .. })));
+            assert_eq!(UNIX_EPOCH, time);
+        }
+
+        // Timestamp { nanos: -1 }
+        let mut time = UNIX_EPOCH;
+        let nanos = [
+            0x0b, 0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        assert!(time
+            .deserialize_field(ProtoType::Message, WireType::Len, &mut &nanos[..])
+            .is_err());
+    }
+
+    #[test]
+    fn system_time_merges_repeated_fields() {
+        let mut time = UNIX_EPOCH;
+        // Timestamp { seconds: -5, nanos: 3 }, then Timestamp { nanos: 7 }.
+        let first = [
+            0x0d, 0x08, 0xfb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01, 0x10, 0x03,
+        ];
+        time.deserialize_field(ProtoType::Message, WireType::Len, &mut &first[..])
+            .unwrap();
+        time.deserialize_field(
+            ProtoType::Message,
+            WireType::Len,
+            &mut &[0x02, 0x10, 0x07][..],
+        )
+        .unwrap();
+        assert_eq!((-5, 7), system_time_to_parts(&time));
+    }
+
+    #[test]
+    fn system_time_serialize_out_of_range() {
+        let time = UNIX_EPOCH + Duration::from_secs(MAX_TIMESTAMP_SECONDS as u64 + 1);
+        assert!(time.serialize(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn system_time_serialize() {
+        let time = UNIX_EPOCH + Duration::new(150, 1);
+        let mut buf: Vec<u8> = Vec::new();
+        time.serialize_field(1, ProtoType::Message, &mut buf)
+            .unwrap();
+        assert_eq!(buf, vec![0x0a, 0x05, 0x08, 0x96, 0x01, 0x10, 0x01]);
+    }
+
+    #[test]
+    fn duration_round_trip() {
+        let duration = Duration::new(3, 250);
+        let mut buf: Vec<u8> = Vec::new();
+        duration.serialize(&mut buf).unwrap();
+        assert_eq!(buf, vec![0x05, 0x08, 0x03, 0x10, 0xfa, 0x01]);
+
+        let mut actual = Duration::ZERO;
+        actual
+            .deserialize_field(ProtoType::Message, WireType::Len, &mut &buf[..])
+            .unwrap();
+        assert_eq!(duration, actual);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::descriptor::{bounded_generics, FieldDesc, Shape};
+use crate::proto_type::option_inner_type;
+
+use proc_macro2::{Ident, TokenStream};
+use quote::quote;
+use regex::Regex;
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, error, fmt, rc::Rc, sync::Arc};
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    Error, Expr, Field, Generics, LitStr, Result, Token,
+};
+
+/// A `#[validate(...)]` rule that a field's value breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Every rule a message breaks, with paths from the validated message, e.g.
+/// `server.port` or `tags[3]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationErrors {
+    pub fn is_empty(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn add(&mut self, path: &str, message: String) {
+        let path = path.to_owned();
+        self.violations.push(Violation { path, message });
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let violations = self
+            .violations
+            .iter()
+            .map(Violation::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "{}", violations.join("; "))
+    }
+}
+
+impl error::Error for ValidationErrors {}
+
+/// Checks a message against the `#[validate(...)]` rules on its fields. The
+/// `Deserialize` derive implements this for every message.
+pub trait Validate {
+    /// Records every broken rule, with paths relative to `path`.
+    fn validate_at(&self, path: &str, errors: &mut ValidationErrors);
+
+    fn validate(&self) -> std::result::Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        self.validate_at("", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+macro_rules! impl_validate_pointer {
+    ($($ptr:ident),* $(,)?) => {
+        $(
+            impl<T: Validate + ?Sized> Validate for $ptr<T> {
+                fn validate_at(&self, path: &str, errors: &mut ValidationErrors) {
+                    (**self).validate_at(path, errors)
+                }
+            }
+        )*
+    };
+}
+
+impl_validate_pointer!(Box, Rc, Arc);
+
+/// What `#[validate(length(...))]` measures: characters of a string, bytes of
+/// `bytes` and items of a repeated field.
+pub trait Length {
+    fn length(&self) -> usize;
+}
+
+impl Length for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl Length for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> Length for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Length for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: Length + ?Sized> Length for Box<T> {
+    fn length(&self) -> usize {
+        (**self).length()
+    }
+}
+
+impl<B: Length + ToOwned + ?Sized> Length for Cow<'_, B> {
+    fn length(&self) -> usize {
+        (**self).length()
+    }
+}
+
+/// Whether `value` matches `pattern`, which the derive has already checked compiles.
+/// Each pattern is compiled once per thread.
+pub fn matches_regex(pattern: &'static str, value: &(impl AsRef<str> + ?Sized)) -> bool {
+    thread_local! {
+        static PATTERNS: RefCell<HashMap<&'static str, Regex>> = RefCell::new(HashMap::new());
+    }
+    PATTERNS.with(|patterns| {
+        patterns
+            .borrow_mut()
+            .entry(pattern)
+            .or_insert_with(|| Regex::new(pattern).expect("invalid regex"))
+            .is_match(value.as_ref())
+    })
+}
+
+/// One rule of a `#[validate(...)]` attribute.
+pub(crate) enum ValidationRule {
+    Required,
+    Nested,
+    Range(Bounds),
+    Length(Bounds),
+    Regex(LitStr),
+}
+
+pub(crate) struct Bounds {
+    min: Option<Expr>,
+    max: Option<Expr>,
+}
+
+impl Parse for ValidationRule {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let rule: Ident = input.parse()?;
+        match rule.to_string().as_str() {
+            "required" => Ok(ValidationRule::Required),
+            "nested" => Ok(ValidationRule::Nested),
+            "range" => Ok(ValidationRule::Range(parse_bounds(&rule, input)?)),
+            "length" => Ok(ValidationRule::Length(parse_bounds(&rule, input)?)),
+            "regex" => {
+                input.parse::<Token![=]>()?;
+                let pattern: LitStr = input.parse()?;
+                if let Err(error) = Regex::new(&pattern.value()) {
+                    return Err(Error::new_spanned(
+                        pattern,
+                        format!("invalid regex: {}", error),
+                    ));
+                }
+                Ok(ValidationRule::Regex(pattern))
+            }
+            _ => Err(Error::new_spanned(
+                &rule,
+                format!("unknown validation rule `{}`", rule),
+            )),
+        }
+    }
+}
+
+/// Parses the `(min = ..., max = ...)` of a `range` or `length` rule.
+fn parse_bounds(rule: &Ident, input: ParseStream) -> Result<Bounds> {
+    let content;
+    parenthesized!(content in input);
+    let mut bounds = Bounds {
+        min: None,
+        max: None,
+    };
+    while !content.is_empty() {
+        let key: Ident = content.parse()?;
+        content.parse::<Token![=]>()?;
+        let value: Expr = content.parse()?;
+        let bound = match key.to_string().as_str() {
+            "min" => &mut bounds.min,
+            "max" => &mut bounds.max,
+            _ => return Err(Error::new_spanned(key, "expected `min` or `max`")),
+        };
+        if bound.replace(value).is_some() {
+            return Err(Error::new_spanned(&key, format!("duplicate `{}`", key)));
+        }
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    if bounds.min.is_none() && bounds.max.is_none() {
+        return Err(Error::new_spanned(
+            rule,
+            format!("`{}` needs a `min` or a `max`", rule),
+        ));
+    }
+    Ok(bounds)
+}
+
+/// Parses the field-level `#[validate(...)]` attributes.
+pub(crate) fn extract_validation_rules(field: &Field) -> Result<Vec<ValidationRule>> {
+    let mut rules = Vec::new();
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("validate"))
+    {
+        let parsed =
+            attr.parse_args_with(Punctuated::<ValidationRule, Token![,]>::parse_terminated)?;
+        for rule in parsed {
+            if let ValidationRule::Required = rule {
+                if option_inner_type(&field.ty).is_none() {
+                    return Err(Error::new_spanned(
+                        attr,
+                        "`required` only applies to `Option` fields",
+                    ));
+                }
+            }
+            rules.push(rule);
+        }
+    }
+    Ok(rules)
+}
+
+impl Bounds {
+    fn checks(&self, value: TokenStream, what: &str) -> TokenStream {
+        let min = self.min.as_ref().map(|min| {
+            let message = format!("{}must be at least {{}}", what);
+            quote! {
+                if #value < #min {
+                    errors.add(path, format!(#message, #min));
+                }
+            }
+        });
+        let max = self.max.as_ref().map(|max| {
+            let message = format!("{}must be at most {{}}", what);
+            quote! {
+                if #value > #max {
+                    errors.add(path, format!(#message, #max));
+                }
+            }
+        });
+        quote! { #min #max }
+    }
+}
+
+impl ValidationRule {
+    /// Whether the rule applies to each item of a repeated field rather than the list.
+    fn applies_to_items(&self) -> bool {
+        !matches!(self, ValidationRule::Length(_))
+    }
+
+    /// Checks `value`, a reference to the field's value, and reports to `path`.
+    fn check(&self) -> TokenStream {
+        match self {
+            ValidationRule::Required => quote! {},
+            ValidationRule::Nested => quote! {
+                zombie::Validate::validate_at(value, path, errors);
+            },
+            ValidationRule::Range(bounds) => bounds.checks(quote! { *value }, ""),
+            ValidationRule::Length(bounds) => {
+                let checks = bounds.checks(quote! { length }, "length ");
+                quote! {
+                    let length = zombie::Length::length(value);
+                    #checks
+                }
+            }
+            ValidationRule::Regex(pattern) => {
+                let message = format!("must match `{}`", pattern.value());
+                quote! {
+                    if !zombie::matches_regex(#pattern, value) {
+                        errors.add(path, #message.to_owned());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FieldDesc {
+    fn validate_calls(&self) -> Vec<TokenStream> {
+        let ident = &self.name;
+        let label = ident.to_string();
+        let shape = self.shape();
+        self.validate
+            .iter()
+            .map(|rule| {
+                let check = rule.check();
+                match &shape {
+                    _ if matches!(rule, ValidationRule::Required) => quote! {
+                        if self.#ident.is_none() {
+                            errors.add(&zombie::field_path(path, #label), "is required".to_owned());
+                        }
+                    },
+                    Shape::Optional(_) => quote! {
+                        if let Some(value) = &self.#ident {
+                            let path = &zombie::field_path(path, #label);
+                            #check
+                        }
+                    },
+                    Shape::Repeated(_) if rule.applies_to_items() => quote! {
+                        for (i, value) in self.#ident.iter().enumerate() {
+                            let path = &format!("{}[{}]", zombie::field_path(path, #label), i);
+                            #check
+                        }
+                    },
+                    _ => quote! {
+                        {
+                            let value = &self.#ident;
+                            let path = &zombie::field_path(path, #label);
+                            #check
+                        }
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn derive_validate_struct(
+    name: &Ident,
+    generics: &Generics,
+    fields: &[FieldDesc],
+) -> TokenStream {
+    let generics = bounded_generics(generics, quote! { zombie::Validate });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let calls = fields
+        .iter()
+        .flat_map(|field| field.validate_calls())
+        .collect::<Vec<_>>();
+    let params = if calls.is_empty() {
+        quote! { _path: &str, _errors: &mut zombie::ValidationErrors }
+    } else {
+        quote! { path: &str, errors: &mut zombie::ValidationErrors }
+    };
+    quote! {
+        #[automatically_derived]
+        impl #impl_generics zombie::Validate for #name #ty_generics #where_clause {
+            fn validate_at(&self, #params) {
+                #(#calls)*
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_counts_characters() {
+        assert_eq!(2, "é!".length());
+        assert_eq!(3, vec![0u8; 3].length());
+    }
+
+    #[test]
+    fn errors_display_every_violation() {
+        let mut errors = ValidationErrors::default();
+        errors.add("port", "must be at least 1".to_owned());
+        errors.add("tags[2]", "must match `^[a-z]+$`".to_owned());
+        assert_eq!(
+            "port: must be at least 1; tags[2]: must match `^[a-z]+$`",
+            errors.to_string()
+        );
+    }
+}
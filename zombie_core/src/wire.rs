@@ -0,0 +1,453 @@
+use crate::{
+    descriptor::MAX_FIELD_NUMBER,
+    deserialize::RECURSION_LIMIT,
+    proto_type::{ProtoType, WireType},
+    redact::{redacting, REDACTED},
+};
+
+/// The fields of a message as they appear on the wire, for reading encoded bytes
+/// without the Rust type. `Serialize::descriptor` returns one for derived messages.
+#[derive(Clone, Debug)]
+pub struct Descriptor {
+    /// The fully-qualified protobuf name, as in `Name::full_name`.
+    pub name: String,
+    pub fields: Vec<FieldDescriptor>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldDescriptor {
+    pub number: u64,
+    pub name: String,
+    pub ty: ProtoType,
+    /// Describes the submessage the field holds, or returns `None` if it holds a
+    /// scalar. A function rather than a value so that messages can contain themselves.
+    pub message: fn() -> Option<Descriptor>,
+    /// Whether the field is `#[sensitive]`, so that `annotate` hides its value.
+    pub sensitive: bool,
+}
+
+impl Descriptor {
+    pub fn field(&self, number: u64) -> Option<&FieldDescriptor> {
+        self.fields.iter().find(|field| field.number == number)
+    }
+}
+
+const BYTES_PER_LINE: usize = 8;
+
+/// How deeply submessages are followed, as when decoding. Past it, values are shown
+/// as raw bytes, which also bounds how often each byte is rescanned by `is_message`.
+const MAX_DEPTH: usize = RECURSION_LIMIT;
+
+/// Renders `bytes` as a hex dump with one line per field: its offset, bytes, field
+/// number, wire type and value. Submessages are indented under their field. With a
+/// descriptor, fields are named and values decoded as their declared types;
+/// without one, length-delimited values are shown as whatever they parse as.
+/// Bytes that can't start a field and values cut short are marked, and end the dump
+/// of the message they are in. Values nested more than 100 deep are left undecoded.
+/// The bytes and value of a sensitive field are shown as `<redacted>`, unless inside
+/// [`unredacted`](crate::redact::unredacted).
+pub fn annotate(bytes: &[u8], descriptor: Option<&Descriptor>) -> String {
+    let mut dump = Dump { out: String::new() };
+    dump.message(bytes, 0, 0, descriptor);
+    dump.out
+}
+
+/// Reads a varint from the start of `bytes`, returning it and its length, or `None`
+/// if it runs past the end or past ten bytes.
+fn read_varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut n = 0u64;
+    for (i, b) in bytes.iter().take(10).enumerate() {
+        n |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((n, i + 1));
+        }
+    }
+    None
+}
+
+/// Reads a tag, rejecting field number 0, numbers past the maximum and the group
+/// wire types.
+fn read_tag(bytes: &[u8]) -> Option<(u64, WireType, usize)> {
+    let (tag, len) = read_varint(bytes)?;
+    let number = tag >> 3;
+    let wiretype = WireType::try_from((tag & 0b111) as u8).ok()?;
+    if number == 0 || number > MAX_FIELD_NUMBER {
+        return None;
+    }
+    Some((number, wiretype, len))
+}
+
+/// Whether `bytes` is a sequence of well-formed fields, which is the best guess at
+/// whether an undescribed length-delimited value is a submessage.
+fn is_message(bytes: &[u8]) -> bool {
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let (_, wiretype, len) = match read_tag(&bytes[pos..]) {
+            Some(tag) => tag,
+            None => return false,
+        };
+        pos += len;
+        let len = match wiretype {
+            WireType::VarInt => match read_varint(&bytes[pos..]) {
+                Some((_, len)) => len,
+                None => return false,
+            },
+            WireType::I64 => 8,
+            WireType::I32 => 4,
+            WireType::Len => match read_varint(&bytes[pos..]) {
+                Some((value_len, len)) => len.saturating_add(value_len as usize),
+                None => return false,
+            },
+        };
+        if len > bytes.len() - pos {
+            return false;
+        }
+        pos += len;
+    }
+    !bytes.is_empty()
+}
+
+fn is_printable(s: &str) -> bool {
+    !s.chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+}
+
+/// Renders a varint as the field's declared type, if that differs from the raw value.
+fn decode_varint(ty: Option<ProtoType>, n: u64) -> Option<String> {
+    match ty? {
+        ProtoType::Int32 if n > i32::MAX as u64 => Some((n as i32).to_string()),
+        ProtoType::Int64 if (n as i64) < 0 => Some((n as i64).to_string()),
+        ProtoType::SInt32 | ProtoType::SInt64 => {
+            Some(((n >> 1) as i64 ^ -((n & 1) as i64)).to_string())
+        }
+        ProtoType::Bool => Some((n != 0).to_string()),
+        _ => None,
+    }
+}
+
+fn decode_fixed64(ty: Option<ProtoType>, n: u64) -> Option<String> {
+    match ty? {
+        ProtoType::Fixed64 => Some(n.to_string()),
+        ProtoType::SFixed64 => Some((n as i64).to_string()),
+        ProtoType::Double => Some(f64::from_bits(n).to_string()),
+        _ => None,
+    }
+}
+
+fn decode_fixed32(ty: Option<ProtoType>, n: u32) -> Option<String> {
+    match ty? {
+        ProtoType::Fixed32 => Some(n.to_string()),
+        ProtoType::SFixed32 => Some((n as i32).to_string()),
+        ProtoType::Float => Some(f32::from_bits(n).to_string()),
+        _ => None,
+    }
+}
+
+/// Renders one value of `wiretype` from the start of `bytes` as the field's
+/// declared type, returning the text and the number of bytes read.
+fn scalar(ty: Option<ProtoType>, wiretype: WireType, bytes: &[u8]) -> Option<(String, usize)> {
+    let (raw, decoded, len) = match wiretype {
+        WireType::VarInt => {
+            let (n, len) = read_varint(bytes)?;
+            (format!("varint {}", n), decode_varint(ty, n), len)
+        }
+        WireType::I64 => {
+            let n = u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?);
+            (format!("i64 {:#018x}", n), decode_fixed64(ty, n), 8)
+        }
+        WireType::I32 => {
+            let n = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?);
+            (format!("i32 {:#010x}", n), decode_fixed32(ty, n), 4)
+        }
+        WireType::Len => return None,
+    };
+    let text = match decoded {
+        Some(decoded) => format!("{} = {}", raw, decoded),
+        None => raw,
+    };
+    Some((text, len))
+}
+
+/// What a length-delimited value holds.
+enum Content {
+    Message(Option<Descriptor>),
+    String,
+    Bytes,
+    Packed(ProtoType),
+}
+
+impl Content {
+    fn of(field: Option<&FieldDescriptor>, value: &[u8]) -> Content {
+        if let Some(field) = field {
+            if let Some(submessage) = (field.message)() {
+                return Content::Message(Some(submessage));
+            }
+            match field.ty {
+                ProtoType::String => return Content::String,
+                ProtoType::Bytes => return Content::Bytes,
+                ty if ty.wire_type() != WireType::Len => return Content::Packed(ty),
+                _ => {}
+            }
+        }
+        // Short text often parses as fields too, so printable text is taken as text.
+        match std::str::from_utf8(value) {
+            Ok(s) if is_printable(s) => Content::String,
+            _ if is_message(value) => Content::Message(None),
+            Ok(_) => Content::String,
+            Err(_) => Content::Bytes,
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Content::Message(Some(submessage)) => format!("message {}", submessage.name),
+            Content::Message(None) => "message".to_owned(),
+            Content::String => "string".to_owned(),
+            Content::Bytes => "bytes".to_owned(),
+            Content::Packed(ty) => format!("packed {}", ty.name()),
+        }
+    }
+}
+
+struct Dump {
+    out: String,
+}
+
+impl Dump {
+    /// Writes `bytes`, which start at `offset`, with `note` beside the first line.
+    fn line(&mut self, offset: usize, depth: usize, bytes: &[u8], note: &str) {
+        let indent = "  ".repeat(depth);
+        let mut chunks = bytes.chunks(BYTES_PER_LINE).collect::<Vec<_>>();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let hex = chunk
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let note = if i == 0 { note } else { "" };
+            let line = format!(
+                "{:06x}  {}{:<width$}  {}",
+                offset + i * BYTES_PER_LINE,
+                indent,
+                hex,
+                note,
+                width = BYTES_PER_LINE * 3 - 1
+            );
+            self.out.push_str(line.trim_end());
+            self.out.push('\n');
+        }
+    }
+
+    /// Dumps the fields in `bytes`, which start at `base` in the whole buffer.
+    fn message(
+        &mut self,
+        bytes: &[u8],
+        base: usize,
+        depth: usize,
+        descriptor: Option<&Descriptor>,
+    ) {
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let (number, wiretype, tag_len) = match read_tag(&bytes[pos..]) {
+                Some(tag) => tag,
+                None => {
+                    let rest = &bytes[pos..];
+                    let note = format!("trailing garbage: {} bytes", rest.len());
+                    self.line(base + pos, depth, rest, &note);
+                    return;
+                }
+            };
+            let field = descriptor.and_then(|descriptor| descriptor.field(number));
+            let label = match field {
+                Some(field) => format!("{} {}", number, field.name),
+                None => number.to_string(),
+            };
+            let ty = field.map(|field| field.ty);
+            let sensitive = field.is_some_and(|field| field.sensitive) && redacting();
+            let start = pos;
+            pos += tag_len;
+
+            if wiretype != WireType::Len {
+                match scalar(ty, wiretype, &bytes[pos..]) {
+                    Some((text, len)) if sensitive => {
+                        // Keep the wire type, as in "varint", and drop the value.
+                        let raw = text.split(' ').next().unwrap_or_default();
+                        let note = format!("{}: {} {}", label, raw, REDACTED);
+                        self.line(base + start, depth, &bytes[start..pos], &note);
+                        pos += len;
+                    }
+                    Some((text, len)) => {
+                        pos += len;
+                        let note = format!("{}: {}", label, text);
+                        self.line(base + start, depth, &bytes[start..pos], &note);
+                    }
+                    None => {
+                        let note = format!("{}: truncated {:?} value", label, wiretype);
+                        self.line(base + start, depth, &bytes[start..], &note);
+                        return;
+                    }
+                }
+                continue;
+            }
+
+            let (len, len_len) = match read_varint(&bytes[pos..]) {
+                Some(len) => len,
+                None => {
+                    let note = format!("{}: truncated length", label);
+                    self.line(base + start, depth, &bytes[start..], &note);
+                    return;
+                }
+            };
+            pos += len_len;
+            let left = bytes.len() - pos;
+            if len > left as u64 {
+                let note = format!("{}: len {}, truncated: {} bytes left", label, len, left);
+                self.line(base + start, depth, &bytes[start..], &note);
+                return;
+            }
+            let value = &bytes[pos..pos + len as usize];
+            let value_base = base + pos;
+            if sensitive {
+                let note = format!("{}: len {}, {}", label, len, REDACTED);
+                self.line(base + start, depth, &bytes[start..pos], &note);
+                pos += len as usize;
+                continue;
+            }
+            if depth >= MAX_DEPTH {
+                let note = format!("{}: len {}, max depth {} reached", label, len, MAX_DEPTH);
+                self.line(base + start, depth, &bytes[start..pos], &note);
+                if !value.is_empty() {
+                    self.line(value_base, depth + 1, value, "");
+                }
+                pos += len as usize;
+                continue;
+            }
+            let content = Content::of(field, value);
+            let note = format!("{}: len {}, {}", label, len, content.name());
+            self.line(base + start, depth, &bytes[start..pos], &note);
+
+            match content {
+                _ if value.is_empty() => {}
+                Content::Message(submessage) => {
+                    self.message(value, value_base, depth + 1, submessage.as_ref())
+                }
+                Content::Packed(ty) => self.packed(value, value_base, depth + 1, ty),
+                Content::String => match std::str::from_utf8(value) {
+                    Ok(s) => self.line(value_base, depth + 1, value, &format!("{:?}", s)),
+                    Err(_) => self.line(value_base, depth + 1, value, "invalid utf-8"),
+                },
+                Content::Bytes => self.line(value_base, depth + 1, value, ""),
+            }
+            pos += len as usize;
+        }
+    }
+
+    fn packed(&mut self, bytes: &[u8], base: usize, depth: usize, ty: ProtoType) {
+        let mut pos = 0;
+        let mut i = 0;
+        while pos < bytes.len() {
+            match scalar(Some(ty), ty.wire_type(), &bytes[pos..]) {
+                Some((text, len)) => {
+                    let note = format!("[{}] {}", i, text);
+                    self.line(base + pos, depth, &bytes[pos..pos + len], &note);
+                    pos += len;
+                    i += 1;
+                }
+                None => {
+                    let note = format!("[{}] truncated {}", i, ty.name());
+                    self.line(base + pos, depth, &bytes[pos..], &note);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotate_without_descriptor() {
+        // 1: 150, 2: "hi", 3: { 1: 1 }, then a byte that can't start a field.
+        let bytes = [
+            0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i', 0x1a, 0x02, 0x08, 0x01, 0x00,
+        ];
+        assert_eq!(
+            annotate(&bytes, None),
+            "\
+000000  08 96 01                 1: varint 150
+000003  12 02                    2: len 2, string
+000005    68 69                    \"hi\"
+000007  1a 02                    3: len 2, message
+000009    08 01                    1: varint 1
+00000b  00                       trailing garbage: 1 bytes
+"
+        );
+    }
+
+    #[test]
+    fn annotate_redacts_sensitive_scalars() {
+        let descriptor = Descriptor {
+            name: "Pin".to_owned(),
+            fields: vec![FieldDescriptor {
+                number: 1,
+                name: "pin".to_owned(),
+                ty: ProtoType::UInt32,
+                message: || None,
+                sensitive: true,
+            }],
+        };
+        let bytes = [0x08, 0x96, 0x01];
+        assert_eq!(
+            annotate(&bytes, Some(&descriptor)),
+            "000000  08                       1 pin: varint <redacted>\n"
+        );
+        assert_eq!(
+            crate::redact::unredacted(|| annotate(&bytes, Some(&descriptor))),
+            "000000  08 96 01                 1 pin: varint 150\n"
+        );
+    }
+
+    #[test]
+    fn annotate_truncated_value() {
+        let bytes = [0x0a, 0x05, b'a', b'b'];
+        assert_eq!(
+            annotate(&bytes, None),
+            "000000  0a 05 61 62              1: len 5, truncated: 2 bytes left\n"
+        );
+        assert_eq!(
+            annotate(&[0x08, 0x96], None),
+            "000000  08 96                    1: truncated VarInt value\n"
+        );
+    }
+
+    #[test]
+    fn annotate_stops_at_max_depth() {
+        // Field 1 nested 50,000 deep, with the lengths worked out from the inside.
+        let mut headers = Vec::new();
+        let mut len = 2;
+        for _ in 0..50_000 {
+            let mut header = vec![0x0a];
+            let mut n = len;
+            while n >= 0x80 {
+                header.push((n as u8) | 0x80);
+                n >>= 7;
+            }
+            header.push(n as u8);
+            len += header.len();
+            headers.push(header);
+        }
+        let mut bytes = headers.into_iter().rev().flatten().collect::<Vec<u8>>();
+        bytes.extend([0x08, 0x01]);
+
+        let out = annotate(&bytes, None);
+        let messages = out.lines().filter(|line| line.ends_with("message"));
+        assert_eq!(MAX_DEPTH, messages.count());
+        let last = out.lines().find(|line| line.contains("max depth")).unwrap();
+        assert!(last.ends_with("0a 85 ec 0b              1: len 194053, max depth 100 reached"));
+    }
+}
@@ -1,14 +1,43 @@
 use proc_macro::TokenStream;
 use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(Serialize, attributes(id, pbtype))]
+#[proc_macro_derive(
+    Serialize,
+    attributes(zombie, id, pbtype, required, default, reserved, sensitive, validate)
+)]
 pub fn derive_serialize(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    proc_macro::TokenStream::from(zombie_core::serialize::derive_serialize(input).unwrap())
+    zombie_core::serialize::derive_serialize(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }
 
-#[proc_macro_derive(Deserialize, attributes(id, pbtype))]
+#[proc_macro_derive(
+    Deserialize,
+    attributes(zombie, id, pbtype, required, default, reserved, sensitive, validate)
+)]
 pub fn derive_deserialize(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    proc_macro::TokenStream::from(zombie_core::deserialize::derive_deserialize(input).unwrap())
+    zombie_core::deserialize::derive_deserialize(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(
+    Diff,
+    attributes(zombie, id, pbtype, required, default, reserved, sensitive, validate)
+)]
+pub fn derive_diff(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    zombie_core::diff::derive_diff(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(Name, attributes(zombie))]
+pub fn derive_name(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    zombie_core::name::derive_name(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
 }